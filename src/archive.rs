@@ -0,0 +1,86 @@
+use crate::cases::read_date_file;
+use crate::config::DataSource;
+use crate::download::SOURCES;
+use crate::error::CoronaDataError;
+use chrono::NaiveDate;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Every download is archived as a snapshot: a copy of all source files in <archive dir>/<Date_file>/,
+// e.g. test-data/archive/2020-12-26/COVID-19_casus_landelijk.json. A snapshot is read with the
+// normal loaders through the DataSource returned by snapshot_source (--snapshot 2020-12-26).
+
+const SNAPSHOT_FORMAT: &str = "%Y-%m-%d";
+
+// copies the current source files into the snapshot of the Date_file of the case file, a file that is
+// already in the snapshot is kept as it is, so a snapshot still shows the data of that day after a
+// later download of one of the other sources
+pub fn archive_snapshot(source: &DataSource) -> Result<Option<NaiveDate>, CoronaDataError> {
+    let date = match read_date_file(&source.cases_path())? {
+        Some(date_file) => date_file.date_naive(),
+        None => return Ok(None),
+    };
+    let dir = snapshot_dir(source, date);
+    fs::create_dir_all(&dir).map_err(|e| CoronaDataError::io(&dir, e))?;
+    for download in SOURCES {
+        let path = (download.path)(source);
+        if !path.exists() {
+            continue;
+        }
+        let target = dir.join(file_name(&path));
+        if target.exists() {
+            continue;
+        }
+        // copied under another name first, an interrupted copy is not taken for the archived file
+        let partial = dir.join(format!("{}.partial", file_name(&path).display()));
+        fs::copy(&path, &partial).map_err(|e| CoronaDataError::io(&partial, e))?;
+        fs::rename(&partial, &target).map_err(|e| CoronaDataError::io(&target, e))?;
+    }
+    Ok(Some(date))
+}
+
+pub fn list_snapshots(source: &DataSource) -> Result<Vec<NaiveDate>, CoronaDataError> {
+    let dir = source.archive_path();
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+    let mut dates: Vec<NaiveDate> = vec![];
+    for entry in fs::read_dir(&dir).map_err(|e| CoronaDataError::io(&dir, e))? {
+        let entry = entry.map_err(|e| CoronaDataError::io(&dir, e))?;
+        if let Some(date) = entry.file_name().to_str().and_then(|name| NaiveDate::parse_from_str(name, SNAPSHOT_FORMAT).ok()) {
+            dates.push(date);
+        }
+    }
+    dates.sort();
+    Ok(dates)
+}
+
+pub fn parse_snapshot_date(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, SNAPSHOT_FORMAT).map_err(|e| format!("'{}': {}, expected a date like 2020-12-26", value, e))
+}
+
+// the data source reading the files of the snapshot of the given date, with its own cache
+pub fn snapshot_source(source: &DataSource, date: NaiveDate) -> Result<DataSource, CoronaDataError> {
+    let dir = snapshot_dir(source, date);
+    if !dir.is_dir() {
+        let available = list_snapshots(source)?.iter().map(|date| date.format(SNAPSHOT_FORMAT).to_string()).collect::<Vec<String>>();
+        let message = format!("no snapshot for {} (available: {})", date.format(SNAPSHOT_FORMAT), available.join(", "));
+        return Err(CoronaDataError::io(&dir, std::io::Error::new(std::io::ErrorKind::NotFound, message)));
+    }
+    let mut snapshot = source.clone();
+    for file in [&mut snapshot.cases_file, &mut snapshot.prevalence_file, &mut snapshot.ic_intake_file,
+                 &mut snapshot.ward_intake_file, &mut snapshot.confirmed_file, &mut snapshot.deaths_file,
+                 &mut snapshot.recovered_file, &mut snapshot.lcps_file] {
+        *file = file_name(file);
+    }
+    snapshot.data_dir = dir;
+    Ok(snapshot)
+}
+
+fn snapshot_dir(source: &DataSource, date: NaiveDate) -> PathBuf {
+    source.archive_path().join(date.format(SNAPSHOT_FORMAT).to_string())
+}
+
+fn file_name(path: &Path) -> PathBuf {
+    path.file_name().map(PathBuf::from).unwrap_or_else(|| path.to_path_buf())
+}
@@ -0,0 +1,105 @@
+use crate::archive::{list_snapshots, snapshot_source};
+use crate::cases::*;
+use crate::config::DataSource;
+use crate::error::CoronaDataError;
+use chrono::{Duration, NaiveDate};
+use std::collections::BTreeMap;
+
+// Reporting delay (backfill) analysis: the count of a Date_statistics keeps growing in every later
+// snapshot, until all cases of that day have been reported. Comparing the archived snapshots gives
+// per delay (days between Date_statistics and Date_file) the fraction of the final count that is
+// already known: the completeness curve. Its increments are the delay distribution.
+
+// a Date_statistics is assumed to be complete in a snapshot taken this many days later
+pub const MAX_DELAY: usize = 21;
+
+pub struct Backfill {
+    // per snapshot (the day of its Date_file) the number of cases per Date_statistics and age group
+    snapshots: BTreeMap<NaiveDate, BTreeMap<NaiveDate, BTreeMap<AgeGroup, usize>>>,
+}
+
+impl Backfill {
+    // the snapshots up to and including until, all of them if it is None
+    pub fn from_archive(source: &DataSource, from: Option<NaiveDate>, until: Option<NaiveDate>) -> Result<Backfill, CoronaDataError> {
+        let mut snapshots = BTreeMap::new();
+        for date in list_snapshots(source)?.into_iter().filter(|date| until.is_none_or(|until| *date <= until)) {
+            let snapshot = snapshot_source(source, date)?;
+            let mut counts: BTreeMap<NaiveDate, BTreeMap<AgeGroup, usize>> = BTreeMap::new();
+            for_each_case(&snapshot.cases_path(), from, |case| {
+                *counts.entry(case.Date_statistics).or_default().entry(case.Agegroup).or_insert(0) += 1;
+            })?;
+            snapshots.insert(date, counts);
+        }
+        Ok(Backfill { snapshots })
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn latest(&self) -> Option<NaiveDate> {
+        self.snapshots.keys().next_back().copied()
+    }
+
+    // None if there is no snapshot of that day, all age groups if group is None
+    fn count(&self, snapshot: NaiveDate, date: NaiveDate, group: Option<AgeGroup>) -> Option<usize> {
+        let counts = self.snapshots.get(&snapshot)?;
+        Some(counts.get(&date).map_or(0, |groups| {
+            groups.iter().filter(|(g, _)| group.is_none_or(|group| group == **g)).map(|(_, &count)| count).sum()
+        }))
+    }
+
+    // how the count of one Date_statistics grew with every later snapshot, as (days later, count)
+    pub fn growth(&self, date: NaiveDate, group: Option<AgeGroup>) -> Vec<(i64, usize)> {
+        self.snapshots.keys().filter(|&&snapshot| snapshot >= date).map(|&snapshot| {
+            ((snapshot - date).num_days(), self.count(snapshot, date, group).unwrap_or(0))
+        }).collect()
+    }
+
+    // per delay, the (count in the snapshot of that delay, final count) of every Date_statistics
+    // that is complete in the latest snapshot and has a snapshot at that delay
+    fn pairs(&self, group: Option<AgeGroup>) -> Vec<Vec<(usize, usize)>> {
+        let mut res: Vec<Vec<(usize, usize)>> = vec![vec![]; MAX_DELAY + 1];
+        let latest = match self.latest() {
+            Some(latest) => latest,
+            None => return res,
+        };
+        let last_complete = latest - Duration::days(MAX_DELAY as i64);
+        for &date in self.snapshots[&latest].keys().filter(|&&date| date <= last_complete) {
+            let complete = self.count(latest, date, group).unwrap_or(0);
+            if complete == 0 {
+                continue;
+            }
+            for (delay, pairs) in res.iter_mut().enumerate() {
+                if let Some(count) = self.count(date + Duration::days(delay as i64), date, group) {
+                    pairs.push((count, complete));
+                }
+            }
+        }
+        res
+    }
+
+    // the fraction of the final count known per delay, NaN where no snapshot has that delay
+    pub fn completeness(&self, group: Option<AgeGroup>) -> Vec<f32> {
+        self.pairs(group).iter().map(|pairs| {
+            let count: usize = pairs.iter().map(|(count, _)| count).sum();
+            let complete: usize = pairs.iter().map(|(_, complete)| complete).sum();
+            count as f32 / complete as f32
+        }).collect()
+    }
+
+    // the completeness of every single Date_statistics per delay, for the spread around the curve
+    pub fn completeness_samples(&self, group: Option<AgeGroup>) -> Vec<Vec<f32>> {
+        self.pairs(group).iter().map(|pairs| {
+            pairs.iter().map(|&(count, complete)| count as f32 / complete as f32).collect()
+        }).collect()
+    }
+
+    // the fraction of the cases that is reported with each delay
+    pub fn delay_distribution(&self, group: Option<AgeGroup>) -> Vec<f32> {
+        let completeness = self.completeness(group);
+        completeness.iter().enumerate().map(|(delay, &fraction)| {
+            if delay == 0 { fraction } else { fraction - completeness[delay - 1] }
+        }).collect()
+    }
+}
@@ -0,0 +1,144 @@
+use crate::cases::*;
+use crate::config::DataSource;
+use crate::cube::CaseCube;
+use crate::error::CoronaDataError;
+use crate::timeseries::TimeSeries;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+// A binary cache of the parsed and aggregated input data. It is only used when every source
+// file still has the same hash and the case file the same Date_file as when the cache was written.
+
+// bump when the layout of ParsedData (or anything in it) changes
+const CACHE_VERSION: u32 = 8;
+
+#[derive(Serialize, Deserialize)]
+pub struct ParsedData {
+    pub cases: CaseCube,
+    pub tests: JhuSeries,
+    pub hospitalizations: TimeSeries<Hospitalization>,
+    pub prevalences: TimeSeries<Prevalence>,
+    pub lcps: Vec<LcpsOccupancy>,
+}
+
+impl ParsedData {
+    pub fn parse(source: &DataSource, from: Option<NaiveDate>) -> Result<ParsedData, CoronaDataError> {
+        Ok(ParsedData {
+            cases: CaseCube::from_source(source, from)?,
+            tests: get_tests(source, from)?,
+            hospitalizations: get_hospitalizations(source, from)?,
+            prevalences: get_prevalences(source, from)?,
+            lcps: get_lcps_occupancy(source, from)?,
+        })
+    }
+
+    // without the days after until
+    pub fn until(mut self, until: NaiveDate) -> ParsedData {
+        self.hospitalizations.split_after(until);
+        self.prevalences.split_after(until);
+        ParsedData {
+            cases: self.cases.until(until),
+            tests: self.tests.until(until),
+            hospitalizations: self.hospitalizations,
+            prevalences: self.prevalences,
+            lcps: self.lcps.into_iter().filter(|occupancy| occupancy.date <= until).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct CacheKey {
+    version: u32,
+    from: Option<NaiveDate>,
+    date_file: Option<DateTime<Utc>>,
+    sources: Vec<(PathBuf, u64)>,
+    hospital_join: Join,
+    gap_fill: GapFill,
+    negative_deltas: DeltaPolicy,
+}
+
+impl CacheKey {
+    fn for_sources(source: &DataSource, from: Option<NaiveDate>) -> Result<CacheKey, CoronaDataError> {
+        let mut paths = vec![source.cases_path(), source.confirmed_path(), source.ic_intake_path(), source.ward_intake_path(), source.prevalence_path()];
+        // the LCPS file is optional, once it is downloaded the cache is rebuilt with it
+        if source.lcps_path().exists() {
+            paths.push(source.lcps_path());
+        }
+        let mut sources: Vec<(PathBuf, u64)> = vec![];
+        for path in paths {
+            let hash = hash_file(&path)?;
+            sources.push((path, hash));
+        }
+        Ok(CacheKey {
+            version: CACHE_VERSION,
+            from,
+            date_file: read_date_file(&source.cases_path())?,
+            sources,
+            hospital_join: source.hospital_join,
+            gap_fill: source.gap_fill,
+            negative_deltas: source.negative_deltas,
+        })
+    }
+}
+
+pub fn load_data(source: &DataSource, from: Option<NaiveDate>, use_cache: bool) -> Result<ParsedData, CoronaDataError> {
+    if !use_cache {
+        return ParsedData::parse(source, from);
+    }
+
+    let cache_path = source.cache_path();
+    let key = CacheKey::for_sources(source, from)?;
+    if let Some(data) = read_cache(&cache_path, &key) {
+        let date_file = key.date_file.map_or(String::from("unknown"), |date_file| date_file.to_string());
+        println!("using cached data from {} (Date_file {})", cache_path.display(), date_file);
+        return Ok(data);
+    }
+
+    let data = ParsedData::parse(source, from)?;
+    if let Err(e) = write_cache(&cache_path, &key, &data) {
+        println!("Warning: could not write the cache: {}", e);
+    }
+    Ok(data)
+}
+
+// any problem reading the cache just means it has to be rebuilt
+fn read_cache(path: &Path, key: &CacheKey) -> Option<ParsedData> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let cached_key: CacheKey = bincode::deserialize_from(&mut reader).ok()?;
+    if cached_key != *key {
+        return None;
+    }
+    bincode::deserialize_from(&mut reader).ok()
+}
+
+fn write_cache(path: &Path, key: &CacheKey, data: &ParsedData) -> Result<(), CoronaDataError> {
+    let temp_path = path.with_extension("tmp");
+    let file = File::create(&temp_path).map_err(|e| CoronaDataError::io(&temp_path, e))?;
+    let mut writer = BufWriter::new(file);
+    let to_io_error = |e: bincode::Error| CoronaDataError::io(&temp_path, std::io::Error::other(e));
+    bincode::serialize_into(&mut writer, key).map_err(to_io_error)?;
+    bincode::serialize_into(&mut writer, data).map_err(to_io_error)?;
+    writer.flush().map_err(|e| CoronaDataError::io(&temp_path, e))?;
+    std::fs::rename(&temp_path, path).map_err(|e| CoronaDataError::io(path, e))
+}
+
+// 64 bit FNV-1a, stable across runs and Rust versions unlike the std hasher
+fn hash_file(path: &Path) -> Result<u64, CoronaDataError> {
+    let mut reader = BufReader::new(File::open(path).map_err(|e| CoronaDataError::io(path, e))?);
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let count = reader.read(&mut buffer).map_err(|e| CoronaDataError::io(path, e))?;
+        if count == 0 {
+            break;
+        }
+        for byte in &buffer[..count] {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    Ok(hash)
+}
@@ -1,47 +1,237 @@
 use std::fs::File;
-use std::io::prelude::*;
 use serde::{Deserialize, Serialize};
+use serde::de::{DeserializeOwned, Deserializer, SeqAccess, Visitor};
 use std::io::BufReader;
-use chrono::{NaiveDate, DateTime, Date};
+use std::path::Path;
+use chrono::{NaiveDate, DateTime};
 use chrono::prelude::*;
-use std::collections::BTreeMap;
-use curl::easy::Easy;
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+use std::cell::Cell;
+use std::fmt;
+use crate::config::DataSource;
+use crate::error::CoronaDataError;
+use crate::series::Series;
+use crate::timeseries::{Calendar, TimeSeries};
+
+// the fields the filters, the group by keys and the query language work on, implemented
+// for the cases themselves and for the aggregated cells of the case cube
+pub trait CaseFields {
+    fn age_group(&self) -> AgeGroup;
+    fn sex(&self) -> Sex;
+    fn province(&self) -> &str;
+    fn municipal_health_service(&self) -> &str;
+    fn hospital_admission(&self) -> YesNoUnknown;
+    fn deceased(&self) -> YesNoUnknown;
+    fn date_statistics_type(&self) -> DateStatisticsType;
+}
 
 pub struct Filters {}
 
 #[allow(dead_code)]
 impl Filters {
     #[inline]
-    pub fn male              (c: &Case) -> bool { c.Sex.eq("Male") }
+    pub fn male<C: CaseFields>              (c: &C) -> bool { c.sex() == Sex::Male }
+    #[inline]
+    pub fn female<C: CaseFields>            (c: &C) -> bool { c.sex() == Sex::Female }
+    #[inline]
+    pub fn alive<C: CaseFields>             (c: &C) -> bool { c.deceased() != YesNoUnknown::Yes }
     #[inline]
-    pub fn female            (c: &Case) -> bool { return c.Sex.eq("Female") }
+    pub fn dead<C: CaseFields>              (c: &C) -> bool { c.deceased() == YesNoUnknown::Yes }
     #[inline]
-    pub fn alive             (c: &Case) -> bool { return !c.Deceased.eq("Yes") }
+    pub fn age_group_0_9<C: CaseFields>     (c: &C) -> bool { c.age_group() == AgeGroup::Age0_9 } // only alive
     #[inline]
-    pub fn dead              (c: &Case) -> bool { return c.Deceased.eq("Yes") }
+    pub fn age_group_10_19<C: CaseFields>   (c: &C) -> bool { c.age_group() == AgeGroup::Age10_19 } // only alive
     #[inline]
-    pub fn age_group_0_9     (c: &Case) -> bool { return c.Agegroup.eq("0-9") } // only alive
+    pub fn age_group_20_29<C: CaseFields>   (c: &C) -> bool { c.age_group() == AgeGroup::Age20_29 } // only alive
     #[inline]
-    pub fn age_group_10_19   (c: &Case) -> bool { return c.Agegroup.eq("10-19") } // only alive
+    pub fn age_group_30_39<C: CaseFields>   (c: &C) -> bool { c.age_group() == AgeGroup::Age30_39 } // only alive
     #[inline]
-    pub fn age_group_20_29   (c: &Case) -> bool { return c.Agegroup.eq("20-29") } // only alive
+    pub fn age_group_40_49<C: CaseFields>   (c: &C) -> bool { c.age_group() == AgeGroup::Age40_49 } // only alive
     #[inline]
-    pub fn age_group_30_39   (c: &Case) -> bool { return c.Agegroup.eq("30-39") } // only alive
+    pub fn age_group_min_50<C: CaseFields>  (c: &C) -> bool { c.age_group() == AgeGroup::Below50 }  // only dead
     #[inline]
-    pub fn age_group_40_49   (c: &Case) -> bool { return c.Agegroup.eq("40-49") } // only alive
+    pub fn age_group_50_59<C: CaseFields>   (c: &C) -> bool { c.age_group() == AgeGroup::Age50_59 }
     #[inline]
-    pub fn age_group_min_50  (c: &Case) -> bool { return c.Agegroup.eq("<50") }  // only dead
+    pub fn age_group_60_69<C: CaseFields>   (c: &C) -> bool { c.age_group() == AgeGroup::Age60_69 }
     #[inline]
-    pub fn age_group_50_59   (c: &Case) -> bool { return c.Agegroup.eq("50-59") }
+    pub fn age_group_70_79<C: CaseFields>   (c: &C) -> bool { c.age_group() == AgeGroup::Age70_79 }
     #[inline]
-    pub fn age_group_60_69   (c: &Case) -> bool { return c.Agegroup.eq("60-69") }
+    pub fn age_group_80_89<C: CaseFields>   (c: &C) -> bool { c.age_group() == AgeGroup::Age80_89 }
     #[inline]
-    pub fn age_group_70_79   (c: &Case) -> bool { return c.Agegroup.eq("70-79") }
+    pub fn age_group_90_plus<C: CaseFields> (c: &C) -> bool { c.age_group() == AgeGroup::Age90Plus }
     #[inline]
-    pub fn age_group_80_89   (c: &Case) -> bool { return c.Agegroup.eq("80-89") }
+    pub fn hospitalized<C: CaseFields>      (c: &C) -> bool { c.hospital_admission() == YesNoUnknown::Yes }
     #[inline]
-    pub fn age_group_90_plus (c: &Case) -> bool { return c.Agegroup.eq("90+") }
+    pub fn not_hospitalized<C: CaseFields>  (c: &C) -> bool { c.hospital_admission() == YesNoUnknown::No }
+    #[inline]
+    pub fn disease_onset<C: CaseFields>     (c: &C) -> bool { c.date_statistics_type() == DateStatisticsType::DiseaseOnset }
+    #[inline]
+    pub fn positive_lab<C: CaseFields>      (c: &C) -> bool { c.date_statistics_type() == DateStatisticsType::PositiveLab }
+    #[inline]
+    pub fn notification<C: CaseFields>      (c: &C) -> bool { c.date_statistics_type() == DateStatisticsType::Notification }
+
+    pub fn province<C: CaseFields>(name: &str) -> impl Fn(&C) -> bool + '_ {
+        move |c: &C| c.province().eq(name)
+    }
+    pub fn municipal_health_service<C: CaseFields>(name: &str) -> impl Fn(&C) -> bool + '_ {
+        move |c: &C| c.municipal_health_service().eq(name)
+    }
+}
+
+pub struct GroupBy {}
+
+#[allow(dead_code)]
+impl GroupBy {
+    pub fn age_group<C: CaseFields>                (c: &C) -> String { c.age_group().as_str().to_string() }
+    pub fn sex<C: CaseFields>                      (c: &C) -> String { format!("{:?}", c.sex()) }
+    pub fn province<C: CaseFields>                 (c: &C) -> String { c.province().to_string() }
+    pub fn municipal_health_service<C: CaseFields> (c: &C) -> String { c.municipal_health_service().to_string() }
+    pub fn hospital_admission<C: CaseFields>       (c: &C) -> String { format!("{:?}", c.hospital_admission()) }
+    pub fn deceased<C: CaseFields>                 (c: &C) -> String { format!("{:?}", c.deceased()) }
+    pub fn date_statistics_type<C: CaseFields>     (c: &C) -> String { c.date_statistics_type().as_str().to_string() }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum AgeGroup {
+    #[serde(rename = "0-9")]
+    Age0_9,
+    #[serde(rename = "10-19")]
+    Age10_19,
+    #[serde(rename = "20-29")]
+    Age20_29,
+    #[serde(rename = "30-39")]
+    Age30_39,
+    #[serde(rename = "40-49")]
+    Age40_49,
+    #[serde(rename = "<50")]
+    Below50,
+    #[serde(rename = "50-59")]
+    Age50_59,
+    #[serde(rename = "60-69")]
+    Age60_69,
+    #[serde(rename = "70-79")]
+    Age70_79,
+    #[serde(rename = "80-89")]
+    Age80_89,
+    #[serde(rename = "90+")]
+    Age90Plus,
+    Unknown,
+}
+
+#[allow(dead_code)]
+impl AgeGroup {
+    // the groups used for the per age group graphs, "<50" only occurs for deceased cases
+    pub const DECADES: [AgeGroup; 10] = [
+        AgeGroup::Age0_9, AgeGroup::Age10_19, AgeGroup::Age20_29, AgeGroup::Age30_39, AgeGroup::Age40_49,
+        AgeGroup::Age50_59, AgeGroup::Age60_69, AgeGroup::Age70_79, AgeGroup::Age80_89, AgeGroup::Age90Plus,
+    ];
+
+    // the groups of the deceased cases, RIVM reports all of them below 50 as <50
+    pub const DECEASED: [AgeGroup; 6] = [
+        AgeGroup::Below50, AgeGroup::Age50_59, AgeGroup::Age60_69, AgeGroup::Age70_79, AgeGroup::Age80_89, AgeGroup::Age90Plus,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AgeGroup::Age0_9    => "0-9",
+            AgeGroup::Age10_19  => "10-19",
+            AgeGroup::Age20_29  => "20-29",
+            AgeGroup::Age30_39  => "30-39",
+            AgeGroup::Age40_49  => "40-49",
+            AgeGroup::Below50   => "<50",
+            AgeGroup::Age50_59  => "50-59",
+            AgeGroup::Age60_69  => "60-69",
+            AgeGroup::Age70_79  => "70-79",
+            AgeGroup::Age80_89  => "80-89",
+            AgeGroup::Age90Plus => "90+",
+            AgeGroup::Unknown   => "Unknown",
+        }
+    }
+}
+
+impl FromStr for AgeGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AgeGroup::DECADES.iter().chain([AgeGroup::Below50, AgeGroup::Unknown].iter())
+            .find(|group| group.as_str().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or(format!("unknown age group '{}'", s))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Sex {
+    Male,
+    Female,
+    Unknown,
+}
+
+impl FromStr for Sex {
+    type Err = String;
 
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "male"    => Ok(Sex::Male),
+            "female"  => Ok(Sex::Female),
+            "unknown" => Ok(Sex::Unknown),
+            _ => Err(format!("unknown sex '{}'", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum YesNoUnknown {
+    Yes,
+    No,
+    Unknown,
+}
+
+impl FromStr for YesNoUnknown {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "yes"     => Ok(YesNoUnknown::Yes),
+            "no"      => Ok(YesNoUnknown::No),
+            "unknown" => Ok(YesNoUnknown::Unknown),
+            _ => Err(format!("expected Yes, No or Unknown, found '{}'", s)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DateStatisticsType {
+    #[serde(rename = "DOO")]
+    DiseaseOnset,
+    #[serde(rename = "DPL")]
+    PositiveLab,
+    #[serde(rename = "DON")]
+    Notification,
+}
+
+impl DateStatisticsType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DateStatisticsType::DiseaseOnset => "DOO",
+            DateStatisticsType::PositiveLab  => "DPL",
+            DateStatisticsType::Notification => "DON",
+        }
+    }
+}
+
+impl FromStr for DateStatisticsType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "DOO" => Ok(DateStatisticsType::DiseaseOnset),
+            "DPL" => Ok(DateStatisticsType::PositiveLab),
+            "DON" => Ok(DateStatisticsType::Notification),
+            _ => Err(format!("unknown date statistics type '{}', expected DOO, DPL or DON", s)),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -51,18 +241,24 @@ pub struct PatientCount {
     pub value: usize,
 }
 
+// NaN for a day without a value when the gaps are filled with GapFill::NaN
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Hospitalization {
     #[serde(with = "my_date_format")]    
     pub Date_statistics: NaiveDate,
-    pub ic_patients: usize,
-    pub rc_patients: usize,
+    pub ic_patients: f32,
+    pub rc_patients: f32,
 }
 
-impl Hospitalization {
-    pub fn name(&self) -> String {
-        self.Date_statistics.format("%Y%m%d").to_string()
-    }
+// a row of lcps-covid-19.csv, the beds occupied by COVID patients, empty values are None
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LcpsOccupancy {
+    #[serde(rename = "Datum", with = "dutch_date_format")]
+    pub date: NaiveDate,
+    #[serde(rename = "IC_Bedden_COVID")]
+    pub ic_beds: Option<usize>,
+    #[serde(rename = "Kliniek_Bedden")]
+    pub ward_beds: Option<usize>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -74,44 +270,41 @@ pub struct Prevalence {
     pub prev_up:  usize
 }
 
-impl Prevalence {
-    pub fn name(&self) -> String {
-        self.Date.format("%Y%m%d").to_string()
-    }
-}
-
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Case {
     #[serde(with = "my_datetime_format")]    
     pub Date_file: DateTime<Utc>,
     #[serde(with = "my_date_format")]    
     pub Date_statistics: NaiveDate,
-    pub Date_statistics_type: String,
-    pub Agegroup: String,
-    pub Sex: String,
+    pub Date_statistics_type: DateStatisticsType,
+    pub Agegroup: AgeGroup,
+    pub Sex: Sex,
     pub Province: String,
-    pub Hospital_admission: String,
-    pub Deceased: String,
+    pub Hospital_admission: YesNoUnknown,
+    pub Deceased: YesNoUnknown,
     pub Week_of_death: Option<String>,
     pub Municipal_health_service: String    
 }
 
 impl Case {
-    pub fn name(&self) -> String {
-        self.Date_statistics.format("%Y%m%d").to_string()
+    // the Monday of the ISO week of death, given as e.g. "202045"
+    pub fn week_of_death(&self) -> Option<NaiveDate> {
+        let week = self.Week_of_death.as_ref()?;
+        if week.len() != 6 {
+            return None;
+        }
+        NaiveDate::from_isoywd_opt(week[..4].parse().ok()?, week[4..].parse().ok()?, Weekday::Mon)
     }
 }
 
-pub fn windowed_average(values: &Vec<f32>, window: usize) -> Vec<f32> {
-    values.iter().enumerate().skip(window).map(|(index, _)| {
-        values[index-window+1..index+1].to_vec().iter().sum::<f32>() / (window as f32)
-    }).collect::<Vec<f32>>()
-}
-
-pub fn active_cases(case_counts: &Vec<f32>, window: usize) -> Vec<f32> {
-    case_counts.iter().enumerate().skip(window).map(|(index, _)| {
-        case_counts[index-window..index+1].to_vec().iter().sum::<f32>()
-    }).collect::<Vec<f32>>()
+impl CaseFields for Case {
+    fn age_group(&self)                -> AgeGroup           { self.Agegroup }
+    fn sex(&self)                      -> Sex                { self.Sex }
+    fn province(&self)                 -> &str               { &self.Province }
+    fn municipal_health_service(&self) -> &str               { &self.Municipal_health_service }
+    fn hospital_admission(&self)       -> YesNoUnknown       { self.Hospital_admission }
+    fn deceased(&self)                 -> YesNoUnknown       { self.Deceased }
+    fn date_statistics_type(&self)     -> DateStatisticsType { self.Date_statistics_type }
 }
 
 pub fn growth_factor(case_counts: &Vec<f32>) -> Vec<f32> {
@@ -120,226 +313,512 @@ pub fn growth_factor(case_counts: &Vec<f32>) -> Vec<f32> {
     }).collect::<Vec<f32>>()
 }
 
-pub fn filter_cases(cases: &BTreeMap<String, Vec<Case>>, filters: &Vec<&dyn Fn(&Case) -> bool>) -> BTreeMap<String, Vec<Case>> {
-    let mut res: BTreeMap<String, Vec<Case>> = BTreeMap::new();
-    let labels = cases.iter().map(|(name,_)| name.clone() ).collect::<Vec<String>>();
+// streams the national case file, the visitor is called for every case after the from date
+pub fn for_each_case<F: FnMut(Case)>(path: &Path, from: Option<NaiveDate>, mut visit: F) -> Result<(), CoronaDataError> {
+    let file = File::open(path).map_err(|e| CoronaDataError::io(path, e))?;
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(file));
 
-    cases.iter().for_each(|(name, cs)| {
-        res.insert(name.clone(), cs.iter().filter(|&c| filters.iter().fold(true, |acc, &func| acc & func(c) )).map(|c| c.clone()).collect::<Vec<Case>>());
-    });
+    let row = Cell::new(0);
+    let visitor = CaseVisitor {
+        visit: |case: Case| if from.is_none() || case.Date_statistics > from.unwrap() { visit(case) },
+        row: &row,
+    };
 
-    labels.iter().for_each(|name| {
-        if !res.contains_key(name) {
-            res.insert(name.clone(), vec![]);
+    deserializer.deserialize_seq(visitor)
+        .and_then(|_| deserializer.end())
+        .map_err(|e| CoronaDataError::json(path, Some(row.get()), e))
+}
+
+// the Date_file of the first case, without reading the rest of the file
+pub fn read_date_file(path: &Path) -> Result<Option<DateTime<Utc>>, CoronaDataError> {
+    let file = File::open(path).map_err(|e| CoronaDataError::io(path, e))?;
+    let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(file));
+
+    let first: Cell<Option<DateTime<Utc>>> = Cell::new(None);
+    let visitor = FirstCaseVisitor { date_file: &first };
+    match deserializer.deserialize_seq(visitor) {
+        Ok(()) => Ok(first.get()),
+        Err(_) if first.get().is_some() => Ok(first.get()), // serde_json complains about the elements left unread
+        Err(e) => Err(CoronaDataError::json(path, Some(0), e)),
+    }
+}
+
+struct FirstCaseVisitor<'a> {
+    date_file: &'a Cell<Option<DateTime<Utc>>>,
+}
+
+impl<'de, 'a> Visitor<'de> for FirstCaseVisitor<'a> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of cases")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        if let Some(case) = seq.next_element::<Case>()? {
+            self.date_file.set(Some(case.Date_file));
+        }
+        Ok(())
+    }
+}
+
+struct CaseVisitor<'a, F: FnMut(Case)> {
+    visit: F,
+    row: &'a Cell<usize>,
+}
+
+impl<'de, 'a, F: FnMut(Case)> Visitor<'de> for CaseVisitor<'a, F> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an array of cases")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(case) = seq.next_element::<Case>()? {
+            (self.visit)(case);
+            self.row.set(self.row.get() + 1);
+        }
+        Ok(())
+    }
+}
+
+pub fn get_prevalences(source: &DataSource, from: Option<NaiveDate>) -> Result<TimeSeries<Prevalence>, CoronaDataError> {
+    Ok(get_prevalence_from_file(source, from)?.into_iter().map(|prev| (prev.Date, prev)).collect())
+}
+
+pub fn get_hospitalizations(source: &DataSource, from: Option<NaiveDate>) -> Result<TimeSeries<Hospitalization>, CoronaDataError> {
+    Ok(get_hospitalizationdata_from_file(source, from)?.into_iter().map(|hospitalization| (hospitalization.Date_statistics, hospitalization)).collect())
+}
+
+// a row of the JHU CSSE time series: a country, or a province of it, e.g. Netherlands/Aruba
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Region {
+    pub country: String,
+    pub province: Option<String>,
+}
+
+impl Region {
+    pub fn country(country: &str) -> Region {
+        Region { country: country.to_string(), province: None }
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.province {
+            Some(province) => write!(f, "{}/{}", self.country, province),
+            None => write!(f, "{}", self.country),
         }
-    });
+    }
+}
 
-    res
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some((country, province)) => Ok(Region { country: country.trim().to_string(), province: Some(province.trim().to_string()) }),
+            None if !s.trim().is_empty() => Ok(Region::country(s.trim())),
+            None => Err("empty region".to_string()),
+        }
+    }
 }
 
-pub fn get_cases(from: Option<Date<Utc>>) -> BTreeMap<String, Vec<Case>> {
-    let mut res: BTreeMap<String, Vec<Case>> = BTreeMap::new();
-    if let Some(cases) = get_data_from_file(from) {
-        cases.iter().for_each(|case| {
-            res.entry(case.name()).or_insert(vec![]).push(case.clone());
-        });
+// a comma separated list of regions, a name with a comma is quoted: Belgium,"Korea, South"
+pub fn parse_regions(s: &str) -> Result<Vec<Region>, String> {
+    let mut names = vec![String::new()];
+    let mut quoted = false;
+    for c in s.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => names.push(String::new()),
+            _ => names.last_mut().unwrap().push(c),
+        }
     }
-    res
+    if quoted {
+        return Err("unterminated quote".to_string());
+    }
+    names.iter().map(|name| name.parse::<Region>()).collect()
+}
+
+// the daily increase of a cumulative JHU series, its last cumulative value, the days where the
+// cumulative value went down and the days that are estimates, not data
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct JhuSeries {
+    pub daily: TimeSeries<i64>,
+    pub total: i64,
+    pub corrections: Vec<DeltaCorrection>,
+    pub estimated: BTreeSet<NaiveDate>,
 }
 
-pub fn get_prevalences(from: Option<Date<Utc>>) -> BTreeMap<String, Prevalence> {
-    let mut res: BTreeMap<String, Prevalence> = BTreeMap::new();
-    if let Some(prevs) = get_prevalence_from_file(from) {
-        prevs.iter().for_each(|prev| {
-            res.insert( prev.name(), prev.clone());
-        });
+impl JhuSeries {
+    pub fn series(&self) -> Series {
+        self.daily.series(|&v| v as f32)
+    }
+
+    // a zero for the days of the calendar without a value, up to the last day of the series
+    pub fn with_calendar(mut self, calendar: &Calendar) -> JhuSeries {
+        self.daily = calendar.fill(&self.daily, 0);
+        self
+    }
+
+    // without the days after until
+    pub fn until(mut self, until: NaiveDate) -> JhuSeries {
+        let later = self.daily.split_after(until);
+        self.total -= later.values().sum::<i64>();
+        self.estimated.retain(|date| *date <= until);
+        self
     }
-    res
+
+    // a value for a day the data does not have yet, flagged as estimated
+    pub fn estimate(&mut self, date: NaiveDate, value: i64) {
+        self.daily.insert(date, value);
+        self.total += value;
+        self.estimated.insert(date);
+    }
+}
+
+// what to do with a negative daily delta, when a cumulative total was revised downward: keep it,
+// clamp it to zero, or take it off the previous days so the sum still matches the total
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeltaPolicy {
+    Keep,
+    Clamp,
+    Spread,
 }
 
-pub fn get_hospitalizations(from: Option<Date<Utc>>) -> BTreeMap<String, Hospitalization> {
-    let mut res: BTreeMap<String, Hospitalization> = BTreeMap::new();
-    if let Some(hospitalizations) = get_hospitalizationdata_from_file(from) {
-        hospitalizations.iter().for_each(|hospitalization| {
-            res.insert( hospitalization.name(), hospitalization.clone());
-        });
+impl FromStr for DeltaPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "keep"   => Ok(DeltaPolicy::Keep),
+            "clamp"  => Ok(DeltaPolicy::Clamp),
+            "spread" => Ok(DeltaPolicy::Spread),
+            _ => Err(format!("unknown policy '{}', expected keep, clamp or spread", s)),
+        }
     }
-    res
 }
 
-pub fn get_tests(from: Option<Date<Utc>>) -> (BTreeMap<String, usize>, usize) {
-    let mut res: BTreeMap<String, usize> = BTreeMap::new();
-    
-    let file = File::open("test-data/time_series_covid19_confirmed_global.csv").unwrap();
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeltaCorrection {
+    pub date: NaiveDate,
+    pub delta: i64,
+    pub policy: DeltaPolicy,
+    // with DeltaPolicy::Spread, the number of previous days it was taken off and what was left
+    // because those days did not have enough
+    pub days: usize,
+    pub remaining: i64,
+}
+
+impl fmt::Display for DeltaCorrection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: daily delta {}", self.date.format("%Y-%m-%d"), self.delta)?;
+        match self.policy {
+            DeltaPolicy::Keep   => write!(f, " kept"),
+            DeltaPolicy::Clamp  => write!(f, " clamped to 0"),
+            DeltaPolicy::Spread if self.remaining == 0 => write!(f, " spread over the previous {} day(s)", self.days),
+            DeltaPolicy::Spread => write!(f, " spread over the previous {} day(s), {} could not be taken off", self.days, self.remaining),
+        }
+    }
+}
+
+pub fn get_tests(source: &DataSource, from: Option<NaiveDate>) -> Result<JhuSeries, CoronaDataError> {
+    let netherlands = Region::country("Netherlands");
+    Ok(get_confirmed(source, from, std::slice::from_ref(&netherlands))?.remove(&netherlands).unwrap_or_default())
+}
+
+pub fn get_confirmed(source: &DataSource, from: Option<NaiveDate>, regions: &[Region]) -> Result<BTreeMap<Region, JhuSeries>, CoronaDataError> {
+    get_jhu_series_from_file(&source.confirmed_path(), from, &|region| regions.contains(region), source.negative_deltas)
+}
+
+pub fn get_deaths(source: &DataSource, from: Option<NaiveDate>, regions: &[Region]) -> Result<BTreeMap<Region, JhuSeries>, CoronaDataError> {
+    get_jhu_series_from_file(&source.deaths_path(), from, &|region| regions.contains(region), source.negative_deltas)
+}
+
+pub fn get_recovered(source: &DataSource, from: Option<NaiveDate>, regions: &[Region]) -> Result<BTreeMap<Region, JhuSeries>, CoronaDataError> {
+    get_jhu_series_from_file(&source.recovered_path(), from, &|region| regions.contains(region), source.negative_deltas)
+}
+
+pub fn log_corrections(path: &Path, region: &Region, series: &JhuSeries) {
+    for correction in &series.corrections {
+        println!("{}: {} {}", path.display(), region, correction);
+    }
+}
+
+// the series of the selected regions of a JHU CSSE time series file, each row is a region with
+// the cumulative count per day in the columns after Lat and Long
+pub fn get_jhu_series_from_file(path: &Path, from: Option<NaiveDate>, select: &dyn Fn(&Region) -> bool, policy: DeltaPolicy) -> Result<BTreeMap<Region, JhuSeries>, CoronaDataError> {
+    let mut res: BTreeMap<Region, JhuSeries> = BTreeMap::new();
+
+    let file = File::open(path).map_err(|e| CoronaDataError::io(path, e))?;
     let mut rdr = csv::Reader::from_reader(file);
 
-    let header_record = rdr.headers().unwrap().clone();
+    let header_record = rdr.headers().map_err(|e| CoronaDataError::csv(path, e))?.clone();
+    let column = |name: &str| header_record.iter().position(|header| header == name)
+        .ok_or_else(|| CoronaDataError::schema(path, 0, name, "missing column".to_string()));
+    let (province_column, country_column) = (column("Province/State")?, column("Country/Region")?);
+
+    let mut dates: Vec<(usize, NaiveDate)> = vec![];
+    for (index, header) in header_record.iter().enumerate() {
+        match header {
+            "Province/State" | "Country/Region" | "Lat" | "Long" => {}
+            _ => {
+                let date = parse_header_date(header).ok_or_else(|| CoronaDataError::schema(path, 0, header, "expected a date as m/d/yy".to_string()))?;
+                dates.push((index, date));
+            }
+        }
+    }
+
+    // the cumulative counts per region, some countries (e.g. Canada, Australia, China) only have rows
+    // per province, their total is the sum of those
+    let mut cumulative: BTreeMap<Region, Vec<i64>> = BTreeMap::new();
+    let mut province_totals: BTreeMap<Region, Vec<i64>> = BTreeMap::new();
+    for (row, result) in rdr.records().enumerate() {
+        let record = result.map_err(|e| CoronaDataError::csv(path, e))?;
+        let province = record.get(province_column).unwrap_or("");
+        let region = Region {
+            country: record.get(country_column).unwrap_or("").to_string(),
+            province: if province.is_empty() { None } else { Some(province.to_string()) },
+        };
+        let country = Region::country(&region.country);
+        let add_to_country = region.province.is_some() && select(&country);
+        if !select(&region) && !add_to_country {
+            continue;
+        }
 
-    let mut total = 0;
-    for result in rdr.records() {
-        let record = result.unwrap();
-        let resrec =  header_record.iter().zip(record.iter());
-        let mut country = String::from("");
+        let mut values: Vec<i64> = vec![];
+        for &(index, _) in &dates {
+            let value = record.get(index).unwrap_or("");
+            values.push(value.parse::<i64>().map_err(|e| CoronaDataError::schema(path, row + 1, &header_record[index], format!("'{}': {}", value, e)))?);
+        }
+        if add_to_country {
+            let totals = province_totals.entry(country).or_insert_with(|| vec![0; dates.len()]);
+            for (total, value) in totals.iter_mut().zip(&values) {
+                *total += value;
+            }
+        }
+        if select(&region) {
+            cumulative.insert(region, values);
+        }
+    }
+    for (country, totals) in province_totals {
+        cumulative.entry(country).or_insert(totals);
+    }
+
+    for (region, values) in cumulative {
+        let mut deltas: Vec<i64> = vec![];
         let mut last = 0;
-        for rr in resrec {
-            match rr.0 {
-                "Province/State" | "Country/Region" => { country.push_str(rr.1); },
-                "Lat" | "Long" => {}
-                _ => {
-                    if country.eq("Netherlands") {
-                        let date_parts = rr.0.split('/').map(|v| v.parse::<usize>().unwrap()).collect::<Vec<usize>>();
-                        let date = Utc.ymd(date_parts[2] as i32 + 2000, date_parts[0] as u32, date_parts[1] as u32);
-                        if (from != None && date > from.unwrap()) || from == None {
-                            let date_str = format!("20{:02}{:02}{:02}", date_parts[2], date_parts[0], date_parts[1]);
-                            let thisvalue = rr.1.parse::<usize>().unwrap();
-                            res.insert(date_str.clone(), thisvalue - last);
-                            total = thisvalue;
-                            last = thisvalue;
-                        }
-                    }
-                } 
+        for thisvalue in values {
+            deltas.push(thisvalue - last);
+            last = thisvalue;
+        }
+        let mut series = JhuSeries { total: last, corrections: correct_deltas(&dates, &mut deltas, policy), ..JhuSeries::default() };
+        for (&(_, date), delta) in dates.iter().zip(deltas) {
+            if from.is_none_or(|from| date > from) {
+                series.daily.insert(date, delta);
+            }
+        }
+        res.insert(region, series);
+    }
+
+    Ok(res)
+}
+
+fn correct_deltas(dates: &[(usize, NaiveDate)], deltas: &mut [i64], policy: DeltaPolicy) -> Vec<DeltaCorrection> {
+    let mut corrections: Vec<DeltaCorrection> = vec![];
+    for index in 0..deltas.len() {
+        let delta = deltas[index];
+        if delta >= 0 {
+            continue;
+        }
+        let mut correction = DeltaCorrection { date: dates[index].1, delta, policy, days: 0, remaining: 0 };
+        match policy {
+            DeltaPolicy::Keep => {}
+            DeltaPolicy::Clamp => deltas[index] = 0,
+            DeltaPolicy::Spread => {
+                deltas[index] = 0;
+                let mut remaining = -delta;
+                let mut previous = index;
+                while remaining > 0 && previous > 0 {
+                    previous -= 1;
+                    let taken = remaining.min(deltas[previous].max(0));
+                    deltas[previous] -= taken;
+                    remaining -= taken;
+                }
+                correction.days = index - previous;
+                correction.remaining = remaining;
             }
         }
+        corrections.push(correction);
+    }
+    corrections
+}
+
+// the JHU CSSE files use m/d/yy dates as column headers
+fn parse_header_date(header: &str) -> Option<NaiveDate> {
+    let date_parts = header.split('/').map(|v| v.parse::<u32>().ok()).collect::<Option<Vec<u32>>>()?;
+    if date_parts.len() != 3 {
+        return None;
     }
+    NaiveDate::from_ymd_opt(date_parts[2] as i32 + 2000, date_parts[0], date_parts[1])
+}
 
-    (res, total)
+pub fn read_json_file<T: DeserializeOwned>(path: &Path) -> Result<T, CoronaDataError> {
+    let file = File::open(path).map_err(|e| CoronaDataError::io(path, e))?;
+    serde_json::from_reader(BufReader::new(file)).map_err(|e| CoronaDataError::json(path, None, e))
 }
 
-pub fn get_data_from_file(from: Option<Date<Utc>>) -> Option<Vec<Case>> {
-    // Open the file in read-only mode with buffer.
+pub fn get_prevalence_from_file(source: &DataSource, from: Option<NaiveDate>) -> Result<Vec<Prevalence>, CoronaDataError> {
+    let prevs: Vec<Prevalence> = read_json_file(&source.prevalence_path())?;
+    match from {
+        Some(from) => Ok(prevs.into_iter().filter(|prev| prev.Date > from).collect()),
+        None => Ok(prevs),
+    }
+}
 
-    if let Ok(file) = File::open("test-data/COVID-19_casus_landelijk.json") {
-        let reader = BufReader::new(file);
+pub fn get_hospitalizationdata_from_file(source: &DataSource, from: Option<NaiveDate>) -> Result<Vec<Hospitalization>, CoronaDataError> {
+    let after_from = |patient: &PatientCount| from.is_none() || patient.date > from.unwrap();
 
-        match serde_json::from_reader(reader) {
-            Ok(cases) => {
-                if from != None {
-                    let all_cases: Vec<Case> = cases;
-                    return Some(all_cases.iter().filter(|&case| {
-                        return case.Date_statistics > from.unwrap().naive_utc() 
-                    }).map(|case| case.clone() ).collect::<Vec<Case>>());
-                }
-                return Some(cases)
-            },
-            Err(e) => println!("Error: {:?}", e)
+    let all_ic: Vec<PatientCount> = read_json_file(&source.ic_intake_path())?;
+    let ic = all_ic.into_iter().filter(after_from).collect::<Vec<PatientCount>>(); // intake_count
+
+    let all_rc: Vec<PatientCount> = read_json_file(&source.ward_intake_path())?;
+    let rc = all_rc.into_iter().filter(after_from).collect::<Vec<PatientCount>>(); // zkh_intake_count
+
+    let (hospitalizations, gaps) = join_by_date(&ic, &rc, source.hospital_join, source.gap_fill);
+    for (path, missing) in [(source.ic_intake_path(), &gaps.ic), (source.ward_intake_path(), &gaps.ward)] {
+        if !missing.is_empty() {
+            let days = missing.iter().map(|date| date.format("%Y-%m-%d").to_string()).collect::<Vec<String>>();
+            println!("Warning: {} has no value for {} day(s): {}", path.display(), days.len(), days.join(", "));
         }
-    } else {
-        println!("Error reading file");
     }
-    None
+
+    Ok(hospitalizations)
 }
 
-pub fn get_prevalence_from_file(from: Option<Date<Utc>>) -> Option<Vec<Prevalence>> {
-    // Open the file in read-only mode with buffer.
+// how the IC and ward intake series are combined: only the days both have, or every day from
+// the first to the last day of either with the missing values filled in
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Join {
+    Inner,
+    Outer,
+}
 
-    if let Ok(file) = File::open("test-data/COVID-19_prevalentie.json") {
-        let reader = BufReader::new(file);
+impl FromStr for Join {
+    type Err = String;
 
-        match serde_json::from_reader(reader) {
-            Ok(prevs) => {
-                if from != None {
-                    let all_prevs: Vec<Prevalence> = prevs;
-                    return Some(all_prevs.iter().filter(|&prev| {
-                        return prev.Date > from.unwrap().naive_utc() 
-                    }).map(|prev| prev.clone() ).collect::<Vec<Prevalence>>());
-                }
-                return Some(prevs)
-            },
-            Err(e) => println!("Error: {:?}", e)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "inner" => Ok(Join::Inner),
+            "outer" => Ok(Join::Outer),
+            _ => Err(format!("unknown join '{}', expected inner or outer", s)),
         }
-    } else {
-        println!("Error reading file");
     }
-    None
 }
 
-pub fn get_hospitalizationdata_from_file(from: Option<Date<Utc>>) -> Option<Vec<Hospitalization>> {
-    let mut ic: Vec<PatientCount> = vec![]; // intake_count
-    let mut rc: Vec<PatientCount> = vec![]; // zkh_intake_count
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum GapFill {
+    Zero,
+    Previous,
+    Interpolate,
+    NaN,
+}
 
-    if let Ok(file) = File::open("test-data/intake_count.json") {
-        let reader = BufReader::new(file);
+impl FromStr for GapFill {
+    type Err = String;
 
-        match serde_json::from_reader(reader) {
-            Ok(patients) => {
-                if from != None {
-                    let all_partients: Vec<PatientCount> = patients;
-                    ic = all_partients.iter().filter(|&patient| {
-                        return patient.date > from.unwrap().naive_utc() 
-                    }).map(|patient| patient.clone() ).collect::<Vec<PatientCount>>();
-                } else {
-                    ic = patients.clone();
-                }
-            },
-            Err(e) => println!("Error: {:?}", e)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "zero"        => Ok(GapFill::Zero),
+            "previous"    => Ok(GapFill::Previous),
+            "interpolate" => Ok(GapFill::Interpolate),
+            "nan"         => Ok(GapFill::NaN),
+            _ => Err(format!("unknown gap fill '{}', expected zero, previous, interpolate or nan", s)),
         }
-    } else {
-        println!("Error reading file");
-    }
-
-    if let Ok(file) = File::open("test-data/zkh_intake_count.json") {
-        let reader = BufReader::new(file);
-
-        match serde_json::from_reader(reader) {
-            Ok(patients) => {
-                if from != None {
-                    let all_partients: Vec<PatientCount> = patients;
-                    rc = all_partients.iter().filter(|&patient| {
-                        return patient.date > from.unwrap().naive_utc() 
-                    }).map(|patient| patient.clone() ).collect::<Vec<PatientCount>>();
-                } else {
-                    rc = patients.clone();
-                }
-            },
-            Err(e) => println!("Error: {:?}", e)
+    }
+}
+
+// the days between the first and the last day of both series that are missing in each of them
+#[derive(Debug, Default)]
+pub struct Gaps {
+    pub ic: Vec<NaiveDate>,
+    pub ward: Vec<NaiveDate>,
+}
+
+pub fn join_by_date(ic: &[PatientCount], ward: &[PatientCount], join: Join, gap_fill: GapFill) -> (Vec<Hospitalization>, Gaps) {
+    let ic = ic.iter().map(|count| (count.date, count.value as f32)).collect::<BTreeMap<NaiveDate, f32>>();
+    let ward = ward.iter().map(|count| (count.date, count.value as f32)).collect::<BTreeMap<NaiveDate, f32>>();
+
+    let first = ic.keys().chain(ward.keys()).min().copied();
+    let last = ic.keys().chain(ward.keys()).max().copied();
+    let all_days = match (first, last) {
+        (Some(first), Some(last)) => first.iter_days().take_while(|date| *date <= last).collect::<Vec<NaiveDate>>(),
+        _ => vec![],
+    };
+
+    let gaps = Gaps {
+        ic: all_days.iter().filter(|date| !ic.contains_key(date)).copied().collect(),
+        ward: all_days.iter().filter(|date| !ward.contains_key(date)).copied().collect(),
+    };
+
+    let days = match join {
+        Join::Inner => all_days.into_iter().filter(|date| ic.contains_key(date) && ward.contains_key(date)).collect(),
+        Join::Outer => all_days,
+    };
+    let hospitalizations = days.iter().map(|&date| Hospitalization {
+        Date_statistics: date,
+        ic_patients: fill_gap(&ic, date, gap_fill),
+        rc_patients: fill_gap(&ward, date, gap_fill),
+    }).collect();
+
+    (hospitalizations, gaps)
+}
+
+// the value of the day, or if the series has none the value the gap fill policy gives it
+fn fill_gap(values: &BTreeMap<NaiveDate, f32>, date: NaiveDate, gap_fill: GapFill) -> f32 {
+    if let Some(&value) = values.get(&date) {
+        return value;
+    }
+    let previous = values.range(..date).next_back();
+    let next = values.range(date..).next();
+    match gap_fill {
+        GapFill::Zero => 0.0,
+        GapFill::NaN => f32::NAN,
+        GapFill::Previous => previous.map_or(f32::NAN, |(_, &value)| value),
+        GapFill::Interpolate => match (previous, next) {
+            (Some((&before, &from)), Some((&after, &to))) =>
+                from + (to - from) * (date - before).num_days() as f32 / (after - before).num_days() as f32,
+            (Some((_, &value)), None) | (None, Some((_, &value))) => value,
+            (None, None) => f32::NAN,
+        },
+    }
+}
+
+// without the LCPS file there is no occupancy, only the lcps graph needs it
+pub fn get_lcps_occupancy(source: &DataSource, from: Option<NaiveDate>) -> Result<Vec<LcpsOccupancy>, CoronaDataError> {
+    if !source.lcps_path().exists() {
+        return Ok(vec![]);
+    }
+    get_lcps_occupancy_from_file(&source.lcps_path(), from)
+}
+
+// sorted by date, the file itself has the most recent day first
+pub fn get_lcps_occupancy_from_file(path: &Path, from: Option<NaiveDate>) -> Result<Vec<LcpsOccupancy>, CoronaDataError> {
+    let mut rdr = csv::Reader::from_path(path).map_err(|e| CoronaDataError::csv(path, e))?;
+    let mut res: Vec<LcpsOccupancy> = vec![];
+    for result in rdr.deserialize() {
+        let occupancy: LcpsOccupancy = result.map_err(|e| CoronaDataError::csv(path, e))?;
+        if from.is_none_or(|from| occupancy.date > from) {
+            res.push(occupancy);
         }
-    } else {
-        println!("Error reading file");
-    }
-
-    // let mut res: BTreeMap<String, usize> = BTreeMap::new();
-
-    // let mut lcps_data: BTreeMap<NaiveDate, (usize, usize)> = BTreeMap::new();
-    
-    // let file = File::open("test-data/lcps-covid-19.csv").unwrap();
-    // let mut rdr = csv::Reader::from_reader(file);
-    // let header_record = rdr.headers().unwrap().clone();
-    // for result in rdr.records() {
-    //     let record = result.unwrap();
-    //     let resrec =  header_record.iter().zip(record.iter());
-    //     let mut date: NaiveDate = NaiveDate::from_ymd(2021,1,1);
-    //     let mut ic_count = 0;
-    //     let mut rc_count = 0;
-    //     for (k, v) in resrec {
-    //         match k {
-    //             "Datum" => { 
-    //                 let parts = v.split("-").map(|s| s.parse::<u32>().unwrap()).collect::<Vec<u32>>();
-    //                 date = NaiveDate::from_ymd(parts[2] as i32, parts[1], parts[0]); 
-    //             },
-    //             "IC_Bedden_COVID" => {
-    //                 ic_count = v.parse::<usize>().unwrap();
-    //             },
-    //             "Kliniek_Bedden" => {
-    //                 rc_count = v.parse::<usize>().unwrap();
-    //             },
-    //             _ => {}
-    //         }
-    //     }
-    //     lcps_data.insert( date, (ic_count,rc_count));
-    // }
-
-    let mut hospitalizations: Vec<Hospitalization> = vec![];
-    let counts = ic.iter().zip(rc.iter());
-    for c in counts {
-        hospitalizations.push( Hospitalization{ Date_statistics: c.0.date, ic_patients: c.0.value, rc_patients: c.1.value } );
-        // if lcps_data.contains_key(&c.0.date) {
-        //     let (ic_count, rc_count) = lcps_data[&c.0.date];
-        //     // println!("{:?} => {} vs {}   ===   {} vs {}", c.0.date, c.0.value, ic_count, c.1.value, rc_count);
-        //     println!("{:?} => {}   ===   {} ", c.0.date, c.0.value as i32 - ic_count as i32, c.1.value as i32 - rc_count as i32);
-        // }
-    }
-
-    Some(hospitalizations)
+    }
+    res.sort_by_key(|occupancy| occupancy.date);
+    Ok(res)
 }
 
 mod my_datetime_format {
@@ -425,61 +904,166 @@ mod my_date_format {
     }
 }
 
-pub fn download_data() {
-    let mut nl_datafile = File::create("test-data/COVID-19_casus_landelijk.json").unwrap();
-    let mut handle = Easy::new();
-    handle.url("https://data.rivm.nl/covid-19/COVID-19_casus_landelijk.json").unwrap();
-    handle.write_function(move |data| {
-        nl_datafile.write_all(data).unwrap();
-        Ok(data.len())
-    }).unwrap();
-    handle.perform().unwrap();    
-
-    let mut prev_datafile = File::create("test-data/COVID-19_prevalentie.json").unwrap();
-    let mut handle = Easy::new();
-    handle.url("https://data.rivm.nl/covid-19/COVID-19_prevalentie.json").unwrap();
-    handle.write_function(move |data| {
-        prev_datafile.write_all(data).unwrap();
-        Ok(data.len())
-    }).unwrap();
-    handle.perform().unwrap();
-
-    let mut nl_rc_intake_datafile = File::create("test-data/zkh_intake_count.json").unwrap();
-    let mut handle = Easy::new();
-    handle.url("https://stichting-nice.nl/covid-19/public/zkh/intake-count/").unwrap();
-    handle.write_function(move |data| {
-        nl_rc_intake_datafile.write_all(data).unwrap();
-        Ok(data.len())
-    }).unwrap();
-    handle.perform().unwrap();    
-
-    let mut nl_ic_intake_datafile = File::create("test-data/intake_count.json").unwrap();
-    let mut handle = Easy::new();
-    handle.url("https://stichting-nice.nl/covid-19/public/intake-count/").unwrap();
-    handle.write_function(move |data| {
-        nl_ic_intake_datafile.write_all(data).unwrap();
-        Ok(data.len())
-    }).unwrap();
-    handle.perform().unwrap();    
-
-    let mut world_datafile = File::create("test-data/time_series_covid19_confirmed_global.csv").unwrap();
-    let mut handle = Easy::new();
-    handle.url("https://raw.githubusercontent.com/CSSEGISandData/COVID-19/master/csse_covid_19_data/csse_covid_19_time_series/time_series_covid19_confirmed_global.csv").unwrap();
-    handle.write_function(move |data| {
-        world_datafile.write_all(data).unwrap();
-        Ok(data.len())
-    }).unwrap();
-    handle.perform().unwrap();    
-
-    let mut lcps_datafile = File::create("test-data/lcps-covid-19.csv").unwrap();
-    let mut handle = Easy::new();
-    handle.url("https://lcps.nu/wp-content/uploads/covid-19.csv").unwrap();
-    handle.write_function(move |data| {
-        lcps_datafile.write_all(data).unwrap();
-        Ok(data.len())
-    }).unwrap();
-    handle.perform().unwrap();    
-
-}
-
-//https://coronadashboard.rijksoverheid.nl/_next/data/No7X2glRgBnqP7XDyyRnD/landelijk/positief-geteste-mensen.json
\ No newline at end of file
+// the dd-mm-yyyy dates of the LCPS file
+mod dutch_date_format {
+    use chrono::{NaiveDate};
+    use serde::{self, Deserialize, Serializer, Deserializer};
+    const FORMAT: &str = "%d-%m-%Y";
+
+    pub fn serialize<S>(
+        date: &NaiveDate,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&date.format(FORMAT).to_string())
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<NaiveDate, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        NaiveDate::parse_from_str(&s, FORMAT).map_err(serde::de::Error::custom)
+    }
+}
+
+//https://coronadashboard.rijksoverheid.nl/_next/data/No7X2glRgBnqP7XDyyRnD/landelijk/positief-geteste-mensen.json
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2020, 12, day).unwrap()
+    }
+
+    fn dates(count: usize) -> Vec<(usize, NaiveDate)> {
+        (0..count).map(|index| (index + 4, date(index as u32 + 1))).collect()
+    }
+
+    #[test]
+    fn correct_deltas_keeps_or_clamps() {
+        let mut deltas = vec![5, -3, 2];
+        let corrections = correct_deltas(&dates(3), &mut deltas, DeltaPolicy::Keep);
+        assert_eq!(deltas, vec![5, -3, 2]);
+        assert_eq!(corrections.len(), 1);
+        assert_eq!((corrections[0].date, corrections[0].delta), (date(2), -3));
+
+        let mut deltas = vec![5, -3, 2];
+        correct_deltas(&dates(3), &mut deltas, DeltaPolicy::Clamp);
+        assert_eq!(deltas, vec![5, 0, 2]);
+    }
+
+    #[test]
+    fn correct_deltas_spreads_over_the_previous_days() {
+        let mut deltas = vec![4, 1, 2, -5, 7];
+        let corrections = correct_deltas(&dates(5), &mut deltas, DeltaPolicy::Spread);
+        assert_eq!(deltas, vec![2, 0, 0, 0, 7]);
+        assert_eq!((corrections[0].days, corrections[0].remaining), (3, 0));
+        assert_eq!(deltas.iter().sum::<i64>(), 4 + 1 + 2 - 5 + 7);
+    }
+
+    #[test]
+    fn correct_deltas_spread_with_too_little_earlier_data() {
+        let mut deltas = vec![3, 2, -10, 1];
+        let corrections = correct_deltas(&dates(4), &mut deltas, DeltaPolicy::Spread);
+        assert_eq!(deltas, vec![0, 0, 0, 1]);
+        assert_eq!((corrections[0].days, corrections[0].remaining), (2, 5));
+
+        // nothing before the first day
+        let mut deltas = vec![-4, 1];
+        let corrections = correct_deltas(&dates(2), &mut deltas, DeltaPolicy::Spread);
+        assert_eq!(deltas, vec![0, 1]);
+        assert_eq!((corrections[0].days, corrections[0].remaining), (0, 4));
+    }
+
+    #[test]
+    fn correct_deltas_spread_skips_negative_earlier_days() {
+        let mut deltas = vec![6, -2, -3];
+        let corrections = correct_deltas(&dates(3), &mut deltas, DeltaPolicy::Spread);
+        assert_eq!(deltas, vec![1, 0, 0]);
+        assert_eq!(corrections.len(), 2);
+        assert_eq!((corrections[1].days, corrections[1].remaining), (2, 0));
+    }
+
+    #[test]
+    fn jhu_country_totals_are_the_sum_of_the_provinces_without_a_country_row() {
+        let path = std::env::temp_dir().join(format!("corona-data-jhu-{}.csv", std::process::id()));
+        std::fs::write(&path, "Province/State,Country/Region,Lat,Long,12/1/20,12/2/20,12/3/20\n\
+            Alberta,Canada,0,0,1,3,6\n\
+            Ontario,Canada,0,0,10,20,30\n\
+            ,Netherlands,0,0,100,150,160\n\
+            Aruba,Netherlands,0,0,5,6,7\n\
+            \"Bonaire, Sint Eustatius and Saba\",Netherlands,0,0,1,1,2\n\
+            ,Belgium,0,0,1,2,3\n").unwrap();
+        let regions = parse_regions("Canada,Netherlands,Canada/Ontario,\"Netherlands/Bonaire, Sint Eustatius and Saba\"").unwrap();
+        let res = get_jhu_series_from_file(&path, None, &|region| regions.contains(region), DeltaPolicy::Keep);
+        std::fs::remove_file(&path).unwrap();
+
+        let res = res.unwrap();
+        let daily = |region: &Region| (res[region].daily.values().copied().collect::<Vec<i64>>(), res[region].total);
+        assert_eq!(res.len(), 4);
+        assert_eq!(daily(&regions[0]), (vec![11, 12, 13], 36));
+        assert_eq!(daily(&regions[1]), (vec![100, 50, 10], 160));
+        assert_eq!(daily(&regions[2]), (vec![10, 10, 10], 30));
+        assert_eq!(daily(&regions[3]), (vec![1, 0, 1], 2));
+    }
+
+    #[test]
+    fn parse_regions_with_a_comma_in_quotes() {
+        assert_eq!(parse_regions("Netherlands, Germany/Bavaria").unwrap(), vec![Region::country("Netherlands"), "Germany/Bavaria".parse().unwrap()]);
+        assert_eq!(parse_regions("\"Korea, South\",Belgium").unwrap(), vec![Region::country("Korea, South"), Region::country("Belgium")]);
+        assert!(parse_regions("\"Korea, South").is_err());
+        assert!(parse_regions("Netherlands,").is_err());
+    }
+
+    fn counts(values: &[(u32, usize)]) -> Vec<PatientCount> {
+        values.iter().map(|&(day, value)| PatientCount { date: date(day), value }).collect()
+    }
+
+    fn patients(hospitalizations: &[Hospitalization]) -> Vec<(NaiveDate, f32, f32)> {
+        hospitalizations.iter().map(|h| (h.Date_statistics, h.ic_patients, h.rc_patients)).collect()
+    }
+
+    #[test]
+    fn join_by_date_inner_keeps_the_days_both_have() {
+        let (hospitalizations, gaps) = join_by_date(&counts(&[(1, 10), (2, 11), (4, 13)]), &counts(&[(2, 20), (3, 21), (4, 22)]), Join::Inner, GapFill::Zero);
+        assert_eq!(patients(&hospitalizations), vec![(date(2), 11.0, 20.0), (date(4), 13.0, 22.0)]);
+        assert_eq!(gaps.ic, vec![date(3)]);
+        assert_eq!(gaps.ward, vec![date(1)]);
+    }
+
+    #[test]
+    fn join_by_date_outer_fills_the_gaps() {
+        let ic = counts(&[(2, 10), (5, 16)]);
+        let ward = counts(&[(1, 20), (2, 20), (3, 20), (4, 20), (5, 20), (6, 20)]);
+        let ic_patients = |gap_fill| join_by_date(&ic, &ward, Join::Outer, gap_fill).0.iter().map(|h| h.ic_patients).collect::<Vec<f32>>();
+
+        assert_eq!(ic_patients(GapFill::Zero), vec![0.0, 10.0, 0.0, 0.0, 16.0, 0.0]);
+        assert_eq!(ic_patients(GapFill::Interpolate), vec![10.0, 10.0, 12.0, 14.0, 16.0, 16.0]);
+        let previous = ic_patients(GapFill::Previous);
+        assert!(previous[0].is_nan());
+        assert_eq!(previous[1..], [10.0, 10.0, 10.0, 16.0, 16.0]);
+        assert_eq!(ic_patients(GapFill::NaN).iter().map(|value| value.is_nan()).collect::<Vec<bool>>(), vec![true, false, true, true, false, true]);
+    }
+
+    #[test]
+    fn fill_gap_interpolate_at_the_edges() {
+        let values = [(date(3), 5.0), (date(5), 9.0)].iter().copied().collect::<BTreeMap<NaiveDate, f32>>();
+        assert_eq!(fill_gap(&values, date(1), GapFill::Interpolate), 5.0);
+        assert_eq!(fill_gap(&values, date(4), GapFill::Interpolate), 7.0);
+        assert_eq!(fill_gap(&values, date(8), GapFill::Interpolate), 9.0);
+        assert!(fill_gap(&BTreeMap::new(), date(4), GapFill::Interpolate).is_nan());
+    }
+
+    #[test]
+    fn join_by_date_of_nothing() {
+        let (hospitalizations, gaps) = join_by_date(&[], &[], Join::Outer, GapFill::Interpolate);
+        assert!(hospitalizations.is_empty());
+        assert!(gaps.ic.is_empty() && gaps.ward.is_empty());
+    }
+}
@@ -0,0 +1,143 @@
+// The command line: corona-data [command] [flags]
+//
+//   download [--source ids]   download the sources (all by default) and archive a snapshot
+//   report                    every graph, <output dir>/index.html and the clusters (the default)
+//   graph <name>              a single graph of GRAPHS in main.rs, without rebuilding index.html
+//   peaks                     print the peaks of the active cases per age group
+//   clusters                  the clusters of peaks, as graphs in the clusters directory
+//   export [--weekly]         the daily series as CSV in <output dir>/series.csv, or the sums per
+//                             ISO week in <output dir>/weekly_series.csv
+//   summary                   print the figures of the last day
+//
+// Every command only accepts the flags that apply to it, see Command::flags.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Download,
+    Report,
+    Graph(String),
+    Peaks,
+    Clusters,
+    Export,
+    Summary,
+}
+
+// flags without a value
+const SWITCHES: &[&str] = &["--no-cache", "--nowcast", "--backfill", "--weekly"];
+
+// where the input files are
+const FILE_FLAGS: &[&str] = &[
+    "--config", "--data-dir", "--cases-file", "--prevalence-file", "--ic-intake-file", "--ward-intake-file",
+    "--confirmed-file", "--deaths-file", "--recovered-file", "--lcps-file", "--archive-dir",
+];
+// how they are read, --nowcast corrects the cases every command reads
+const LOAD_FLAGS: &[&str] = &["--cache-file", "--no-cache", "--snapshot", "--hospital-join", "--gap-fill", "--negative-deltas", "--nowcast"];
+const RANGE_FLAGS: &[&str] = &["--from", "--until"];
+const COHORT_FLAGS: &[&str] = &["--cohort", "--cohort-file"];
+const WINDOW_FLAGS: &[&str] = &["--active-window", "--average-window", "--growth-window"];
+const GRAPH_FLAGS: &[&str] = &["--output-dir", "--countries", "--backfill"];
+
+impl Command {
+    pub fn from_args(args: &[String]) -> Result<Command, String> {
+        let positional = positional_args(args);
+        let graphs = crate::graph_names();
+        let command = match positional.as_slice() {
+            [] => Command::Report,
+            ["download"] => Command::Download,
+            ["report"] => Command::Report,
+            ["graph"] => return Err(format!("graph needs the name of a graph, one of: {}", graphs.join(", "))),
+            ["graph", name] if graphs.contains(name) => Command::Graph(name.to_string()),
+            ["graph", name] => return Err(format!("unknown graph '{}', expected one of: {}", name, graphs.join(", "))),
+            ["peaks"] => Command::Peaks,
+            ["clusters"] => Command::Clusters,
+            ["export"] => Command::Export,
+            ["summary"] => Command::Summary,
+            [command] => return Err(format!("unknown command '{}', expected one of: download, report, graph <name>, peaks, clusters, export, summary", command)),
+            _ => return Err(format!("unexpected arguments: {}", positional.join(" "))),
+        };
+
+        let flags = command.flags();
+        if let Some(flag) = args.iter().skip(1).find(|arg| arg.starts_with("--") && !flags.contains(&arg.as_str())) {
+            return Err(format!("{} does not take {}, its flags are: {}", command.name(), flag, flags.join(" ")));
+        }
+        Ok(command)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Download => "download",
+            Command::Report   => "report",
+            Command::Graph(_) => "graph",
+            Command::Peaks    => "peaks",
+            Command::Clusters => "clusters",
+            Command::Export   => "export",
+            Command::Summary  => "summary",
+        }
+    }
+
+    pub fn flags(&self) -> Vec<&'static str> {
+        let groups: Vec<&[&str]> = match self {
+            Command::Download => vec![FILE_FLAGS, &["--source"]],
+            Command::Report   => vec![FILE_FLAGS, LOAD_FLAGS, RANGE_FLAGS, COHORT_FLAGS, WINDOW_FLAGS, GRAPH_FLAGS, &["--template-dir", "--clusters-dir"]],
+            Command::Graph(_) => vec![FILE_FLAGS, LOAD_FLAGS, RANGE_FLAGS, COHORT_FLAGS, WINDOW_FLAGS, GRAPH_FLAGS],
+            Command::Peaks    => vec![FILE_FLAGS, LOAD_FLAGS, RANGE_FLAGS, WINDOW_FLAGS],
+            Command::Clusters => vec![FILE_FLAGS, LOAD_FLAGS, RANGE_FLAGS, WINDOW_FLAGS, &["--clusters-dir"]],
+            Command::Export   => vec![FILE_FLAGS, LOAD_FLAGS, RANGE_FLAGS, COHORT_FLAGS, &["--output-dir", "--weekly"]],
+            Command::Summary  => vec![FILE_FLAGS, LOAD_FLAGS, RANGE_FLAGS, COHORT_FLAGS, WINDOW_FLAGS],
+        };
+        groups.concat()
+    }
+}
+
+// the arguments that are neither a flag nor the value of one
+fn positional_args(args: &[String]) -> Vec<&str> {
+    let mut positional = vec![];
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        if arg.starts_with("--") {
+            if !SWITCHES.contains(&arg.as_str()) {
+                iter.next();
+            }
+        } else {
+            positional.push(arg.as_str());
+        }
+    }
+    positional
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        std::iter::once("corona-data").chain(line.split_whitespace()).map(String::from).collect()
+    }
+
+    #[test]
+    fn positional_args_skip_the_values_of_flags_but_not_switches() {
+        assert_eq!(positional_args(&args("--from 2020-10-01 graph --no-cache active_cases")), vec!["graph", "active_cases"]);
+        assert_eq!(positional_args(&args("--nowcast --backfill report")), vec!["report"]);
+        assert_eq!(positional_args(&args("--data-dir report")), Vec::<&str>::new());
+        assert_eq!(positional_args(&args("")), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn from_args_commands() {
+        assert_eq!(Command::from_args(&args("")), Ok(Command::Report));
+        assert_eq!(Command::from_args(&args("--weekly export")), Ok(Command::Export));
+        assert_eq!(Command::from_args(&args("--until 2020-12-01 graph countries --countries Belgium")), Ok(Command::Graph("countries".to_string())));
+        assert!(Command::from_args(&args("graph")).unwrap_err().contains("active_cases"));
+        assert!(Command::from_args(&args("graph nope")).unwrap_err().starts_with("unknown graph 'nope'"));
+        assert!(Command::from_args(&args("plot")).unwrap_err().starts_with("unknown command 'plot'"));
+        assert!(Command::from_args(&args("peaks summary")).unwrap_err().starts_with("unexpected arguments"));
+    }
+
+    #[test]
+    fn from_args_rejects_the_flags_of_other_commands() {
+        assert!(Command::from_args(&args("peaks --nowcast")).is_ok());
+        assert!(Command::from_args(&args("peaks --output-dir out")).unwrap_err().starts_with("peaks does not take --output-dir"));
+        assert!(Command::from_args(&args("download --weekly")).is_err());
+        assert!(Command::from_args(&args("report --weekly")).is_err());
+        assert!(Command::from_args(&args("report --no-such-flag")).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use crate::cases::{DeltaPolicy, GapFill, Join};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+// Where the input files are read from and where the graphs are written to.
+//
+// Defaults match the original layout (test-data/, graphs/, clusters/ and template/). These can be
+// overridden by a JSON config file (--config <file>) and then by the command line flags
+// (--data-dir, --output-dir, --cases-file, ...). Relative file names and the archive directory are
+// resolved against the data directory.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct DataSource {
+    pub data_dir: PathBuf,
+    pub output_dir: PathBuf,
+    pub clusters_dir: PathBuf,
+    pub template_dir: PathBuf,
+    pub cases_file: PathBuf,
+    pub prevalence_file: PathBuf,
+    pub ic_intake_file: PathBuf,
+    pub ward_intake_file: PathBuf,
+    pub confirmed_file: PathBuf,
+    pub deaths_file: PathBuf,
+    pub recovered_file: PathBuf,
+    pub lcps_file: PathBuf,
+    pub cache_file: PathBuf,
+    pub archive_dir: PathBuf,
+    pub hospital_join: Join,
+    pub gap_fill: GapFill,
+    pub negative_deltas: DeltaPolicy,
+    // the analysis window: the days after from, up to and including until
+    pub from: NaiveDate,
+    pub until: Option<NaiveDate>,
+    pub windows: Windows,
+}
+
+// the number of days of the calculations: the days a case counts as active (infectious), the days of
+// the averages of new cases and the days over which the growth factor is averaged
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(default)]
+pub struct Windows {
+    pub active: usize,
+    pub average: usize,
+    pub growth: usize,
+}
+
+impl Default for Windows {
+    fn default() -> Self {
+        Windows { active: 10, average: 3, growth: 5 }
+    }
+}
+
+impl Default for DataSource {
+    fn default() -> Self {
+        DataSource {
+            data_dir:         PathBuf::from("test-data"),
+            output_dir:       PathBuf::from("graphs"),
+            clusters_dir:     PathBuf::from("clusters"),
+            template_dir:     PathBuf::from("template"),
+            cases_file:       PathBuf::from("COVID-19_casus_landelijk.json"),
+            prevalence_file:  PathBuf::from("COVID-19_prevalentie.json"),
+            ic_intake_file:   PathBuf::from("intake_count.json"),
+            ward_intake_file: PathBuf::from("zkh_intake_count.json"),
+            confirmed_file:   PathBuf::from("time_series_covid19_confirmed_global.csv"),
+            deaths_file:      PathBuf::from("time_series_covid19_deaths_global.csv"),
+            recovered_file:   PathBuf::from("time_series_covid19_recovered_global.csv"),
+            lcps_file:        PathBuf::from("lcps-covid-19.csv"),
+            cache_file:       PathBuf::from("parsed_data.cache"),
+            archive_dir:      PathBuf::from("archive"),
+            hospital_join:    Join::Inner,
+            gap_fill:         GapFill::NaN,
+            negative_deltas:  DeltaPolicy::Clamp,
+            from:             NaiveDate::from_ymd_opt(2020, 2, 27).unwrap(),
+            until:            None,
+            windows:          Windows::default(),
+        }
+    }
+}
+
+impl DataSource {
+    pub fn from_args(args: &[String]) -> Result<DataSource, String> {
+        let flag_value = |flag: &str| -> Option<&String> {
+            args.windows(2).rev().find(|w| w[0] == flag).map(|w| &w[1])
+        };
+
+        let mut source = match flag_value("--config") {
+            Some(filename) => DataSource::from_file(Path::new(filename))?,
+            None => DataSource::default(),
+        };
+
+        let settings: Vec<(&str, &mut PathBuf)> = vec![
+            ("--data-dir",         &mut source.data_dir),
+            ("--output-dir",       &mut source.output_dir),
+            ("--clusters-dir",     &mut source.clusters_dir),
+            ("--template-dir",     &mut source.template_dir),
+            ("--cases-file",       &mut source.cases_file),
+            ("--prevalence-file",  &mut source.prevalence_file),
+            ("--ic-intake-file",   &mut source.ic_intake_file),
+            ("--ward-intake-file", &mut source.ward_intake_file),
+            ("--confirmed-file",   &mut source.confirmed_file),
+            ("--deaths-file",      &mut source.deaths_file),
+            ("--recovered-file",   &mut source.recovered_file),
+            ("--lcps-file",        &mut source.lcps_file),
+            ("--cache-file",       &mut source.cache_file),
+            ("--archive-dir",      &mut source.archive_dir),
+        ];
+        for (flag, setting) in settings {
+            if let Some(value) = flag_value(flag) {
+                *setting = PathBuf::from(value);
+            }
+        }
+
+        if let Some(value) = flag_value("--hospital-join") {
+            source.hospital_join = value.parse()?;
+        }
+        if let Some(value) = flag_value("--gap-fill") {
+            source.gap_fill = value.parse()?;
+        }
+        if let Some(value) = flag_value("--negative-deltas") {
+            source.negative_deltas = value.parse()?;
+        }
+        let parse_date = |value: &str| NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|e| format!("'{}': {}, expected a date like 2020-02-27", value, e));
+        if let Some(value) = flag_value("--from") {
+            source.from = parse_date(value)?;
+        }
+        if let Some(value) = flag_value("--until") {
+            source.until = Some(parse_date(value)?);
+        }
+
+        let windows: Vec<(&str, &mut usize)> = vec![
+            ("--active-window",  &mut source.windows.active),
+            ("--average-window", &mut source.windows.average),
+            ("--growth-window",  &mut source.windows.growth),
+        ];
+        for (flag, window) in windows {
+            if let Some(value) = flag_value(flag) {
+                *window = match value.parse::<usize>() {
+                    Ok(days) if days > 0 => days,
+                    _ => return Err(format!("{} '{}': expected a number of days", flag, value)),
+                };
+            }
+        }
+
+        Ok(source)
+    }
+
+    pub fn from_file(filename: &Path) -> Result<DataSource, String> {
+        let file = File::open(filename).map_err(|e| format!("{}: {}", filename.display(), e))?;
+        serde_json::from_reader(BufReader::new(file)).map_err(|e| format!("{}: {}", filename.display(), e))
+    }
+
+    fn data_path(&self, filename: &Path) -> PathBuf {
+        self.data_dir.join(filename)
+    }
+
+    pub fn cases_path(&self)       -> PathBuf { self.data_path(&self.cases_file) }
+    pub fn prevalence_path(&self)  -> PathBuf { self.data_path(&self.prevalence_file) }
+    pub fn ic_intake_path(&self)   -> PathBuf { self.data_path(&self.ic_intake_file) }
+    pub fn ward_intake_path(&self) -> PathBuf { self.data_path(&self.ward_intake_file) }
+    pub fn confirmed_path(&self)   -> PathBuf { self.data_path(&self.confirmed_file) }
+    pub fn deaths_path(&self)      -> PathBuf { self.data_path(&self.deaths_file) }
+    pub fn recovered_path(&self)   -> PathBuf { self.data_path(&self.recovered_file) }
+    pub fn lcps_path(&self)        -> PathBuf { self.data_path(&self.lcps_file) }
+    pub fn cache_path(&self)       -> PathBuf { self.data_path(&self.cache_file) }
+    pub fn archive_path(&self)     -> PathBuf { self.data_path(&self.archive_dir) }
+
+    pub fn output_path(&self, filename: &str)   -> PathBuf { self.output_dir.join(filename) }
+    pub fn clusters_path(&self, filename: &str) -> PathBuf { self.clusters_dir.join(filename) }
+    pub fn template_path(&self, filename: &str) -> PathBuf { self.template_dir.join(filename) }
+}
@@ -0,0 +1,218 @@
+use crate::cases::*;
+use crate::config::DataSource;
+use crate::error::CoronaDataError;
+use crate::nowcast::{Band, Nowcast};
+use crate::series::Series;
+use crate::timeseries::{Calendar, TimeSeries};
+use chrono::{NaiveDate, DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// All cases aggregated once into counts per day and per combination of the case fields
+// (age group x sex x province x GGD x hospital admission x deceased x date type).
+// Every series used by the graphs is a sum over the cells of each day.
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CubeKey {
+    pub age_group: AgeGroup,
+    pub sex: Sex,
+    pub province: String,
+    pub municipal_health_service: String,
+    pub hospital_admission: YesNoUnknown,
+    pub deceased: YesNoUnknown,
+    pub date_statistics_type: DateStatisticsType,
+    // the Monday of the week of death of the deceased cases
+    pub week_of_death: Option<NaiveDate>,
+}
+
+impl CubeKey {
+    pub fn from_case(case: &Case) -> Self {
+        CubeKey {
+            age_group: case.Agegroup,
+            sex: case.Sex,
+            province: case.Province.clone(),
+            municipal_health_service: case.Municipal_health_service.clone(),
+            hospital_admission: case.Hospital_admission,
+            deceased: case.Deceased,
+            date_statistics_type: case.Date_statistics_type,
+            week_of_death: case.week_of_death(),
+        }
+    }
+}
+
+impl CaseFields for CubeKey {
+    fn age_group(&self)                -> AgeGroup           { self.age_group }
+    fn sex(&self)                      -> Sex                { self.sex }
+    fn province(&self)                 -> &str               { &self.province }
+    fn municipal_health_service(&self) -> &str               { &self.municipal_health_service }
+    fn hospital_admission(&self)       -> YesNoUnknown       { self.hospital_admission }
+    fn deceased(&self)                 -> YesNoUnknown       { self.deceased }
+    fn date_statistics_type(&self)     -> DateStatisticsType { self.date_statistics_type }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CaseCube {
+    pub date_file: Option<DateTime<Utc>>,
+    // the number of cases with a Date_statistics before the analysis window
+    pub before: usize,
+    #[serde(with = "cells_format")]
+    cells: BTreeMap<NaiveDate, BTreeMap<CubeKey, usize>>,
+    // nowcast factors per age group for the most recent days, empty unless with_nowcast was used
+    #[serde(skip)]
+    corrections: BTreeMap<NaiveDate, BTreeMap<AgeGroup, Band>>,
+}
+
+impl CaseCube {
+    pub fn from_source(source: &DataSource, from: Option<NaiveDate>) -> Result<CaseCube, CoronaDataError> {
+        let mut cube = CaseCube::default();
+        for_each_case(&source.cases_path(), None, |case| {
+            if from.is_none_or(|from| case.Date_statistics > from) {
+                cube.add(&case);
+            } else {
+                cube.before += 1;
+            }
+        })?;
+        Ok(cube)
+    }
+
+    // without the days after until
+    pub fn until(mut self, until: NaiveDate) -> CaseCube {
+        self.cells.retain(|date, _| *date <= until);
+        self.corrections.retain(|date, _| *date <= until);
+        self
+    }
+
+    // an entry for every day of the calendar, the days without cases have no cells
+    pub fn with_calendar(mut self, calendar: &Calendar) -> CaseCube {
+        for date in calendar.days() {
+            self.cells.entry(date).or_default();
+        }
+        self
+    }
+
+    pub fn add(&mut self, case: &Case) {
+        if self.date_file.is_none_or(|date_file| date_file < case.Date_file) {
+            self.date_file = Some(case.Date_file);
+        }
+        *self.cells.entry(case.Date_statistics).or_default().entry(CubeKey::from_case(case)).or_insert(0) += 1;
+    }
+
+    pub fn dates(&self) -> Vec<NaiveDate> {
+        self.cells.keys().copied().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn total(&self) -> usize {
+        self.cells.values().map(|day| day.values().sum::<usize>()).sum()
+    }
+
+    // the cube with the counts of the most recent days corrected for the cases that are not reported yet
+    pub fn with_nowcast(mut self, nowcast: &Nowcast) -> CaseCube {
+        let reported = match self.date_file {
+            Some(date_file) => date_file.date_naive(),
+            None => return self,
+        };
+        for date in self.cells.keys() {
+            let groups = AgeGroup::DECADES.iter().chain([AgeGroup::Below50, AgeGroup::Unknown].iter())
+                .filter_map(|&group| nowcast.factor(group, (reported - *date).num_days()).map(|band| (group, band)))
+                .collect::<BTreeMap<AgeGroup, Band>>();
+            if !groups.is_empty() {
+                self.corrections.insert(*date, groups);
+            }
+        }
+        self
+    }
+
+    // the number of cases per day for the cells matching the filter, one value for every date in the cube
+    pub fn series(&self, filter: &dyn Fn(&CubeKey) -> bool) -> Series {
+        if self.corrections.is_empty() {
+            return Series::new(self.dates(), self.cells.values().map(|day| {
+                day.iter().filter(|(key, _)| filter(key)).map(|(_, &count)| count).sum::<usize>() as f32
+            }).collect());
+        }
+        self.corrected_series(filter, |band| band.mid)
+    }
+
+    // the lower and upper bound of the nowcast of the series, None without a nowcast
+    pub fn series_band(&self, filter: &dyn Fn(&CubeKey) -> bool) -> Option<(Series, Series)> {
+        if self.corrections.is_empty() {
+            return None;
+        }
+        Some((self.corrected_series(filter, |band| band.low), self.corrected_series(filter, |band| band.high)))
+    }
+
+    fn corrected_series(&self, filter: &dyn Fn(&CubeKey) -> bool, factor: fn(&Band) -> f32) -> Series {
+        Series::new(self.dates(), self.cells.iter().map(|(date, day)| {
+            let corrections = self.corrections.get(date);
+            day.iter().filter(|(key, _)| filter(key)).map(|(key, &count)| {
+                count as f32 * corrections.and_then(|groups| groups.get(&key.age_group)).map_or(1.0, factor)
+            }).sum::<f32>()
+        }).collect())
+    }
+
+    pub fn all(&self) -> Series {
+        self.series(&|_| true)
+    }
+
+    // the deaths of the cells matching the filter per week of death as reported by the RIVM, dated
+    // by the Monday of the week, the deaths without a week of death are left out
+    pub fn deaths_by_week_of_death(&self, filter: &dyn Fn(&CubeKey) -> bool) -> Series {
+        let mut weeks: TimeSeries<f32> = TimeSeries::default();
+        for day in self.cells.values() {
+            for (key, &count) in day.iter().filter(|(key, _)| filter(key)) {
+                if let Some(week) = key.week_of_death {
+                    *weeks.entry(week).or_insert(0.0) += count as f32;
+                }
+            }
+        }
+        weeks.series(|&v| v)
+    }
+
+    // one series per distinct value of the key
+    pub fn group_series(&self, key: &dyn Fn(&CubeKey) -> String) -> BTreeMap<String, Series> {
+        let mut res: BTreeMap<String, Vec<f32>> = BTreeMap::new();
+        for (index, day) in self.cells.values().enumerate() {
+            for (cell, &count) in day {
+                res.entry(key(cell)).or_insert_with(|| vec![0.0; self.cells.len()])[index] += count as f32;
+            }
+        }
+        res.into_iter().map(|(name, values)| (name, Series::new(self.dates(), values))).collect()
+    }
+}
+
+// serialized as a list of (key, count) pairs per date, so formats that only allow string
+// map keys (like JSON) can hold the cube as well
+mod cells_format {
+    use super::CubeKey;
+    use chrono::NaiveDate;
+    use serde::{Deserialize, Serialize, Serializer, Deserializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S>(
+        cells: &BTreeMap<NaiveDate, BTreeMap<CubeKey, usize>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let pairs = cells.iter().map(|(date, day)| (*date, day.iter().collect::<Vec<(&CubeKey, &usize)>>())).collect::<Vec<_>>();
+        pairs.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D>(
+        deserializer: D,
+    ) -> Result<BTreeMap<NaiveDate, BTreeMap<CubeKey, usize>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let pairs = Vec::<(NaiveDate, Vec<(CubeKey, usize)>)>::deserialize(deserializer)?;
+        Ok(pairs.into_iter().map(|(date, day)| (date, day.into_iter().collect())).collect())
+    }
+}
@@ -0,0 +1,331 @@
+use crate::cases::*;
+use crate::config::DataSource;
+use crate::error::CoronaDataError;
+use curl::easy::{Easy, List, TimeCondition};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// Downloads go to a temporary file next to the target, which only replaces the target once the
+// whole body has been received with a 2xx status and the parser of the source accepts it. A failed
+// download keeps the previous copy. Unchanged files are skipped using If-Modified-Since (from the
+// modification time of the file) and If-None-Match (from the ETag stored next to it in <file>.etag).
+
+const MAX_ATTEMPTS: u32 = 4;
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub enum Download {
+    Updated { bytes: u64 },
+    NotModified,
+}
+
+// Every source that can be downloaded, with the file it is stored in and the parser used to
+// check a new download before it replaces the previous copy. Select them with --source id,id,...
+pub struct Source {
+    pub id: &'static str,
+    pub url: &'static str,
+    pub path: fn(&DataSource) -> PathBuf,
+    pub parse: fn(&Path) -> Result<(), CoronaDataError>,
+}
+
+pub const SOURCES: &[Source] = &[
+    Source { id: "rivm-cases",      url: "https://data.rivm.nl/covid-19/COVID-19_casus_landelijk.json",        path: DataSource::cases_path,       parse: parse_cases },
+    Source { id: "rivm-prevalence", url: "https://data.rivm.nl/covid-19/COVID-19_prevalentie.json",            path: DataSource::prevalence_path,  parse: parse_prevalence },
+    Source { id: "nice-ward",       url: "https://stichting-nice.nl/covid-19/public/zkh/intake-count/",        path: DataSource::ward_intake_path, parse: parse_intake },
+    Source { id: "nice-ic",         url: "https://stichting-nice.nl/covid-19/public/intake-count/",            path: DataSource::ic_intake_path,   parse: parse_intake },
+    Source { id: "jhu-confirmed",   url: "https://raw.githubusercontent.com/CSSEGISandData/COVID-19/master/csse_covid_19_data/csse_covid_19_time_series/time_series_covid19_confirmed_global.csv",
+                                                                                                               path: DataSource::confirmed_path,   parse: parse_jhu },
+    Source { id: "jhu-deaths",      url: "https://raw.githubusercontent.com/CSSEGISandData/COVID-19/master/csse_covid_19_data/csse_covid_19_time_series/time_series_covid19_deaths_global.csv",
+                                                                                                               path: DataSource::deaths_path,      parse: parse_jhu },
+    Source { id: "jhu-recovered",   url: "https://raw.githubusercontent.com/CSSEGISandData/COVID-19/master/csse_covid_19_data/csse_covid_19_time_series/time_series_covid19_recovered_global.csv",
+                                                                                                               path: DataSource::recovered_path,   parse: parse_jhu },
+    Source { id: "lcps",            url: "https://lcps.nu/wp-content/uploads/covid-19.csv",                    path: DataSource::lcps_path,        parse: parse_lcps },
+];
+
+pub fn find_source(id: &str) -> Option<&'static Source> {
+    SOURCES.iter().find(|source| source.id == id)
+}
+
+fn parse_cases(path: &Path) -> Result<(), CoronaDataError> {
+    for_each_case(path, None, |_| ())
+}
+
+fn parse_prevalence(path: &Path) -> Result<(), CoronaDataError> {
+    read_json_file::<Vec<Prevalence>>(path).map(|_| ())
+}
+
+fn parse_intake(path: &Path) -> Result<(), CoronaDataError> {
+    read_json_file::<Vec<PatientCount>>(path).map(|_| ())
+}
+
+fn parse_jhu(path: &Path) -> Result<(), CoronaDataError> {
+    get_jhu_series_from_file(path, None, &|_| true, DeltaPolicy::Keep).map(|_| ())
+}
+
+fn parse_lcps(path: &Path) -> Result<(), CoronaDataError> {
+    get_lcps_occupancy_from_file(path, None).map(|_| ())
+}
+
+// a source that fails is reported and keeps its previous copy, the other sources are still downloaded
+pub fn download_data(source: &DataSource, selected: &[&Source]) -> Vec<(&'static str, CoronaDataError)> {
+    let mut failures = vec![];
+    for download in selected {
+        if let Err(e) = download_file(download.url, &(download.path)(source), download.parse) {
+            failures.push((download.id, e));
+        }
+    }
+    failures
+}
+
+pub fn download_file(url: &str, path: &Path, parse: fn(&Path) -> Result<(), CoronaDataError>) -> Result<Download, CoronaDataError> {
+    let start = Instant::now();
+    let mut attempt = 1;
+    loop {
+        match try_download(url, path, parse) {
+            Ok(download) => {
+                match download {
+                    Download::Updated { bytes } =>
+                        println!("{}: {} bytes in {:.1}s", path.display(), bytes, start.elapsed().as_secs_f32()),
+                    Download::NotModified =>
+                        println!("{}: not modified ({:.1}s)", path.display(), start.elapsed().as_secs_f32()),
+                }
+                return Ok(download);
+            }
+            Err(e) if attempt < MAX_ATTEMPTS && is_transient(&e) => {
+                let delay = FIRST_RETRY_DELAY * 2u32.pow(attempt - 1);
+                println!("{} (attempt {} of {}), retrying in {}s", e, attempt, MAX_ATTEMPTS, delay.as_secs());
+                thread::sleep(delay);
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// connection problems, server errors and rate limiting may go away by themselves, anything else will not
+fn is_transient(error: &CoronaDataError) -> bool {
+    match error {
+        CoronaDataError::Http { .. } => true,
+        CoronaDataError::HttpStatus { status, .. } => *status >= 500 || *status == 429,
+        _ => false,
+    }
+}
+
+fn try_download(url: &str, path: &Path, parse: fn(&Path) -> Result<(), CoronaDataError>) -> Result<Download, CoronaDataError> {
+    let temp_path = with_suffix(path, ".download");
+    let etag_path = with_suffix(path, ".etag");
+    let http_error = |e| CoronaDataError::http(url, e);
+
+    let mut handle = Easy::new();
+    handle.url(url).map_err(http_error)?;
+    handle.follow_location(true).map_err(http_error)?;
+    handle.fetch_filetime(true).map_err(http_error)?;
+    if path.exists() {
+        let modified = fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        if let Some(seconds) = modified.and_then(|modified| modified.duration_since(UNIX_EPOCH).ok()) {
+            handle.time_condition(TimeCondition::IfModifiedSince).map_err(http_error)?;
+            handle.time_value(seconds.as_secs() as i64).map_err(http_error)?;
+        }
+        if let Ok(etag) = fs::read_to_string(&etag_path) {
+            let mut headers = List::new();
+            headers.append(&format!("If-None-Match: {}", etag.trim())).map_err(http_error)?;
+            handle.http_headers(headers).map_err(http_error)?;
+        }
+    }
+
+    let mut datafile = File::create(&temp_path).map_err(|e| CoronaDataError::io(&temp_path, e))?;
+    let mut write_error: Option<std::io::Error> = None;
+    let mut etag: Option<String> = None;
+    let mut bytes: u64 = 0;
+    let result = {
+        let mut transfer = handle.transfer();
+        transfer.write_function(|data| {
+            match datafile.write_all(data) {
+                Ok(()) => { bytes += data.len() as u64; Ok(data.len()) }
+                Err(e) => { write_error = Some(e); Ok(0) } // returning less than data.len() aborts the transfer
+            }
+        }).map_err(http_error)?;
+        transfer.header_function(|header| {
+            let header = String::from_utf8_lossy(header);
+            if let Some((name, value)) = header.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("etag") {
+                    etag = Some(value.trim().to_string());
+                }
+            }
+            true
+        }).map_err(http_error)?;
+        transfer.perform()
+    };
+
+    let status = result.and_then(|_| handle.response_code());
+    let status = match (status, write_error) {
+        (_, Some(e)) => Err(CoronaDataError::io(&temp_path, e)),
+        (Err(e), None) => Err(CoronaDataError::http(url, e)),
+        (Ok(status), None) => Ok(status),
+    };
+    let status = match status {
+        Ok(status) => status,
+        Err(e) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+    };
+
+    if status == 304 || handle.time_condition_unmet().unwrap_or(false) {
+        let _ = fs::remove_file(&temp_path);
+        return Ok(Download::NotModified);
+    }
+    if !(200..300).contains(&status) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(CoronaDataError::HttpStatus { url: url.to_string(), status });
+    }
+
+    datafile.sync_all().map_err(|e| CoronaDataError::io(&temp_path, e))?;
+    // keep the modification time of the server, so the next If-Modified-Since compares like with like
+    if let Ok(Some(filetime)) = handle.filetime() {
+        if filetime >= 0 {
+            let _ = datafile.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(filetime as u64));
+        }
+    }
+    drop(datafile);
+    if let Err(e) = parse(&temp_path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e);
+    }
+    fs::rename(&temp_path, path).map_err(|e| CoronaDataError::io(path, e))?;
+
+    match etag {
+        Some(etag) => fs::write(&etag_path, etag).map_err(|e| CoronaDataError::io(&etag_path, e))?,
+        None => { let _ = fs::remove_file(&etag_path); }
+    }
+    Ok(Download::Updated { bytes })
+}
+
+// e.g. test-data/intake_count.json.download
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    // a local HTTP server that answers each connection with the next response, the requests it
+    // received are sent back once all responses are used
+    fn serve(responses: Vec<String>) -> (String, mpsc::Receiver<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/data.json", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let mut requests = vec![];
+            for response in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 || line == "\r\n" {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+                requests.push(request);
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            sender.send(requests).unwrap();
+        });
+        (url, receiver)
+    }
+
+    fn response(status: &str, headers: &str, body: &str) -> String {
+        format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n{}", status, body.len(), headers, body)
+    }
+
+    // accepts the bodies that start with "ok"
+    fn parse_ok(path: &Path) -> Result<(), CoronaDataError> {
+        match fs::read_to_string(path) {
+            Ok(body) if body.starts_with("ok") => Ok(()),
+            Ok(body) => Err(CoronaDataError::schema(path, 0, "body", format!("'{}'", body))),
+            Err(e) => Err(CoronaDataError::io(path, e)),
+        }
+    }
+
+    // a data file in a directory of its own, with the previous copy if given
+    fn data_file(name: &str, previous: Option<&str>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("corona-data-download-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("data.json");
+        if let Some(previous) = previous {
+            fs::write(&path, previous).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn server_error_is_retried() {
+        let path = data_file("retry", None);
+        let (url, requests) = serve(vec![
+            response("500 Internal Server Error", "", "busy"),
+            response("200 OK", "ETag: \"v1\"\r\n", "ok v1"),
+        ]);
+        assert!(matches!(download_file(&url, &path, parse_ok), Ok(Download::Updated { bytes: 5 })));
+        assert_eq!(requests.recv().unwrap().len(), 2);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ok v1");
+        assert_eq!(fs::read_to_string(with_suffix(&path, ".etag")).unwrap(), "\"v1\"");
+        assert!(!with_suffix(&path, ".download").exists());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn not_modified_leaves_the_file_untouched() {
+        let path = data_file("not-modified", Some("ok v1"));
+        fs::write(with_suffix(&path, ".etag"), "\"v1\"").unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        let (url, requests) = serve(vec![response("304 Not Modified", "", "")]);
+
+        assert!(matches!(try_download(&url, &path, parse_ok), Ok(Download::NotModified)));
+        let request = requests.recv().unwrap().remove(0).to_lowercase();
+        assert!(request.contains("if-none-match: \"v1\""));
+        assert!(request.contains("if-modified-since:"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ok v1");
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+        assert!(!with_suffix(&path, ".download").exists());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn not_found_keeps_the_previous_copy() {
+        let path = data_file("not-found", Some("ok v1"));
+        let (url, _requests) = serve(vec![response("404 Not Found", "", "gone")]);
+
+        match try_download(&url, &path, parse_ok) {
+            Err(e) => assert!(!is_transient(&e)),
+            Ok(_) => panic!("a 404 is not a download"),
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ok v1");
+        assert!(!with_suffix(&path, ".download").exists());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn body_that_fails_to_parse_keeps_the_previous_copy() {
+        let path = data_file("parse-error", Some("ok v1"));
+        fs::write(with_suffix(&path, ".etag"), "\"v1\"").unwrap();
+        let (url, _requests) = serve(vec![response("200 OK", "ETag: \"v2\"\r\n", "<html>maintenance</html>")]);
+
+        assert!(matches!(try_download(&url, &path, parse_ok), Err(CoronaDataError::Schema { .. })));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ok v1");
+        assert_eq!(fs::read_to_string(with_suffix(&path, ".etag")).unwrap(), "\"v1\"");
+        assert!(!with_suffix(&path, ".download").exists());
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
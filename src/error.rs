@@ -0,0 +1,68 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum CoronaDataError {
+    Io     { path: PathBuf, source: std::io::Error },
+    Json   { path: PathBuf, row: Option<usize>, source: serde_json::Error },
+    Csv    { path: PathBuf, source: csv::Error },
+    Http   { url: String, source: curl::Error },
+    HttpStatus { url: String, status: u32 },
+    Schema { path: PathBuf, row: usize, field: String, message: String },
+}
+
+impl CoronaDataError {
+    pub fn io(path: &Path, source: std::io::Error) -> Self {
+        CoronaDataError::Io { path: path.to_path_buf(), source }
+    }
+
+    pub fn json(path: &Path, row: Option<usize>, source: serde_json::Error) -> Self {
+        CoronaDataError::Json { path: path.to_path_buf(), row, source }
+    }
+
+    pub fn csv(path: &Path, source: csv::Error) -> Self {
+        CoronaDataError::Csv { path: path.to_path_buf(), source }
+    }
+
+    pub fn http(url: &str, source: curl::Error) -> Self {
+        CoronaDataError::Http { url: url.to_string(), source }
+    }
+
+    pub fn schema(path: &Path, row: usize, field: &str, message: String) -> Self {
+        CoronaDataError::Schema { path: path.to_path_buf(), row, field: field.to_string(), message }
+    }
+}
+
+impl fmt::Display for CoronaDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoronaDataError::Io { path, source } =>
+                write!(f, "{}: {}", path.display(), source),
+            CoronaDataError::Json { path, row: Some(row), source } =>
+                write!(f, "{}: row {}: {}", path.display(), row, source),
+            CoronaDataError::Json { path, row: None, source } =>
+                write!(f, "{}: {}", path.display(), source),
+            CoronaDataError::Csv { path, source } =>
+                write!(f, "{}: {}", path.display(), source),
+            CoronaDataError::Http { url, source } =>
+                write!(f, "{}: {}", url, source),
+            CoronaDataError::HttpStatus { url, status } =>
+                write!(f, "{}: HTTP status {}", url, status),
+            CoronaDataError::Schema { path, row, field, message } =>
+                write!(f, "{}: row {}, field '{}': {}", path.display(), row, field, message),
+        }
+    }
+}
+
+impl std::error::Error for CoronaDataError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CoronaDataError::Io { source, .. }   => Some(source),
+            CoronaDataError::Json { source, .. } => Some(source),
+            CoronaDataError::Csv { source, .. }  => Some(source),
+            CoronaDataError::Http { source, .. } => Some(source),
+            CoronaDataError::HttpStatus { .. }   => None,
+            CoronaDataError::Schema { .. }       => None,
+        }
+    }
+}
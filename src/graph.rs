@@ -0,0 +1,245 @@
+use crate::error::CoronaDataError;
+use crate::series::Series;
+use chrono::NaiveDate;
+use plotly::common::{DashType, Fill, Font, Line, Mode, Side, Title};
+use plotly::layout::{Axis, AxisType, BarMode, Layout};
+use plotly::{NamedColor, Plot, Scatter};
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+// A graph is declared as a GraphSpec: its traces, the transforms applied to every trace, the titles,
+// the axes and the name of the output. render writes <output dir>/<name>.html and the div for
+// index.html, e.g.
+//
+//    GraphSpec::new("new_cases", "New cases (3 day average)", "New cases")
+//        .transform(Transform::Average(3))
+//        .trace(Trace::new("All", all_cases.all()))
+//
+// The traces are dated series, so they stay on the right days whatever the transforms drop.
+
+#[derive(Debug, Clone)]
+pub enum Transform {
+    // the average of the last n days
+    Average(usize),
+    // the sum of the last n+1 days, the cases that are still infectious
+    Active(usize),
+    // each value divided by the one of the day before
+    Growth,
+    // each value divided by the factor of the same day
+    Divide(Series),
+    // the sum per ISO week
+    Weekly,
+}
+
+impl Transform {
+    pub fn apply(&self, series: &Series) -> Series {
+        match self {
+            Transform::Average(window) => series.rolling_mean(*window),
+            Transform::Active(window) => series.active(*window),
+            Transform::Growth => series.ratio(),
+            Transform::Divide(factors) => series.divide(factors),
+            Transform::Weekly => series.weekly(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Data {
+    // on a date axis that shows the last days by default
+    Series(Series),
+    Numbers(Vec<i64>, Vec<f32>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Trace {
+    name: String,
+    data: Data,
+    // whether the transforms of the graph apply
+    transformed: bool,
+    markers: bool,
+    dotted: bool,
+    fill: bool,
+    y2: bool,
+}
+
+impl Trace {
+    // an empty name leaves the trace unnamed
+    pub fn new(name: &str, series: Series) -> Trace {
+        Trace { name: name.to_string(), data: Data::Series(series), transformed: true, markers: false, dotted: false, fill: false, y2: false }
+    }
+
+    // a series that is shown as it is, without the transforms of the graph
+    pub fn raw(name: &str, series: Series) -> Trace {
+        Trace { transformed: false, ..Trace::new(name, series) }
+    }
+
+    pub fn numbers(name: &str, x: Vec<i64>, y: Vec<f32>) -> Trace {
+        Trace { data: Data::Numbers(x, y), transformed: false, ..Trace::new(name, Series::default()) }
+    }
+
+    pub fn markers(mut self) -> Trace {
+        self.markers = true;
+        self
+    }
+
+    pub fn dotted(mut self) -> Trace {
+        self.dotted = true;
+        self
+    }
+
+    // fills the area down to the previous trace
+    pub fn fill(mut self) -> Trace {
+        self.fill = true;
+        self
+    }
+
+    // on the second y axis, on the right
+    pub fn y2(mut self) -> Trace {
+        self.y2 = true;
+        self
+    }
+
+    fn style<X: Serialize + Clone + 'static>(&self, mut scatter: Box<Scatter<X, f32>>) -> Box<Scatter<X, f32>> {
+        if !self.name.is_empty() {
+            scatter = scatter.name(&self.name);
+        }
+        if self.markers {
+            scatter = scatter.mode(Mode::Markers);
+        }
+        if self.dotted {
+            scatter = scatter.line(Line::new().dash(DashType::Dot));
+        }
+        if self.fill {
+            scatter = scatter.fill(Fill::ToNextY);
+        }
+        if self.y2 {
+            scatter = scatter.y_axis("y2");
+        }
+        scatter
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GraphSpec {
+    name: &'static str,
+    div: &'static str,
+    title: String,
+    x_title: String,
+    y_title: String,
+    y2_title: Option<String>,
+    // the number of dates shown by default on a date axis, weeks for a weekly graph
+    days_shown: usize,
+    transforms: Vec<Transform>,
+    traces: Vec<Trace>,
+}
+
+impl GraphSpec {
+    pub fn new(name: &'static str, title: &str, y_title: &str) -> GraphSpec {
+        GraphSpec {
+            name,
+            div: name,
+            title: title.to_string(),
+            x_title: "Day".to_string(),
+            y_title: y_title.to_string(),
+            y2_title: None,
+            days_shown: 30,
+            transforms: vec![],
+            traces: vec![],
+        }
+    }
+
+    // the id of the div in index.html, the name by default
+    pub fn div(mut self, div: &'static str) -> GraphSpec {
+        self.div = div;
+        self
+    }
+
+    pub fn x_title(mut self, title: &str) -> GraphSpec {
+        self.x_title = title.to_string();
+        self
+    }
+
+    pub fn y2_title(mut self, title: &str) -> GraphSpec {
+        self.y2_title = Some(title.to_string());
+        self
+    }
+
+    pub fn days_shown(mut self, days: usize) -> GraphSpec {
+        self.days_shown = days;
+        self
+    }
+
+    pub fn transform(mut self, transform: Transform) -> GraphSpec {
+        self.transforms.push(transform);
+        self
+    }
+
+    pub fn trace(mut self, trace: Trace) -> GraphSpec {
+        self.traces.push(trace);
+        self
+    }
+
+    pub fn traces(mut self, traces: impl IntoIterator<Item = Trace>) -> GraphSpec {
+        self.traces.extend(traces);
+        self
+    }
+
+    // the series after all transforms
+    pub fn apply(&self, series: &Series) -> Series {
+        self.transforms.iter().fold(series.clone(), |series, transform| transform.apply(&series))
+    }
+
+    pub fn plot(&self) -> Plot {
+        let data = self.traces.iter().map(|trace| match &trace.data {
+            Data::Series(series) if trace.transformed => Data::Series(self.apply(series)),
+            data => data.clone(),
+        }).collect::<Vec<Data>>();
+        let title_font = |size: usize| Font::new().color(NamedColor::Black).size(size).family("Droid Serif");
+
+        let x_axis = Axis::new().title(Title::new(&self.x_title).font(title_font(12)));
+        let days = data.iter().flat_map(|data| match data {
+            Data::Series(series) => series.dates().to_vec(),
+            Data::Numbers(..) => vec![],
+        }).collect::<BTreeSet<NaiveDate>>();
+        let x_axis = match (days.iter().rev().nth(self.days_shown).or(days.iter().next()), days.iter().next_back()) {
+            (Some(begin), Some(end)) => x_axis.type_(AxisType::Date).range(vec![begin.format("%Y-%m-%d").to_string(), end.format("%Y-%m-%d").to_string()]),
+            _ => x_axis,
+        };
+        let mut layout = Layout::new().bar_mode(BarMode::Group)
+            .title(Title::new(&self.title).font(title_font(24)))
+            .x_axis(x_axis)
+            .y_axis(Axis::new().title(Title::new(&self.y_title).font(title_font(12))));
+        if let Some(y2_title) = &self.y2_title {
+            layout = layout.y_axis2(Axis::new().title(Title::new(y2_title).font(title_font(12))).overlaying("y").side(Side::Right));
+        }
+
+        let mut plot = Plot::new();
+        for (trace, data) in self.traces.iter().zip(data) {
+            match data {
+                Data::Series(series) => plot.add_trace(trace.style(Scatter::new(series.labels(), series.values().to_vec()))),
+                Data::Numbers(x, y) => plot.add_trace(trace.style(Scatter::new(x, y))),
+            }
+        }
+        plot.set_layout(layout);
+        plot
+    }
+
+    // writes <dir>/<name>.html and the div of the graph to overview
+    // the div goes to the index.html of dir
+    pub fn render(&self, dir: &Path, overview: &mut dyn Write) -> Result<(), CoronaDataError> {
+        if self.traces.is_empty() {
+            println!("{}: no data", self.name);
+            return Ok(());
+        }
+        // plotly panics when it cannot create the file, the directory at least exists
+        fs::create_dir_all(dir).map_err(|e| CoronaDataError::io(dir, e))?;
+        let plot = self.plot();
+        plot.to_html(dir.join(format!("{}.html", self.name)));
+        let overview_path = dir.join("index.html");
+        overview.write_all(plot.to_inline_html(Some(self.div)).as_bytes()).map_err(|e| CoronaDataError::io(&overview_path, e))?;
+        overview.write_all(b"\n").map_err(|e| CoronaDataError::io(&overview_path, e))
+    }
+}
@@ -1,327 +1,753 @@
+mod archive;
+mod backfill;
+mod cache;
 mod cases;
+mod command;
+mod config;
+mod cube;
+mod download;
+mod error;
+mod graph;
+mod nowcast;
+mod query;
+mod series;
+mod timeseries;
 
 use std::env;
+use archive::{archive_snapshot, parse_snapshot_date, snapshot_source};
+use backfill::{Backfill, MAX_DELAY};
+use cache::load_data;
 use cases::*;
-use chrono::{Date, Utc, Duration};
+use command::Command;
+use config::DataSource;
+use cube::{CaseCube, CubeKey};
+use download::{download_data, find_source, Source, SOURCES};
+use error::CoronaDataError;
+use graph::{GraphSpec, Trace, Transform};
+use nowcast::Nowcast;
+use query::*;
+use series::Series;
+use timeseries::{iso_week, Calendar, TimeSeries};
+use chrono::Duration;
 use chrono::prelude::*;
-use plotly::common::{Title, Font};
-use plotly::layout::{Axis, BarMode, Layout, AxisType };
-use plotly::{Scatter, NamedColor, Plot};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::File;
 use std::io::{BufReader, Write};
 use std::io::prelude::*;
+use std::path::Path;
 use linreg::linear_regression_of;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.contains(&"download".to_string()) { download_data(); }
+    let command = match Command::from_args(&args) {
+        Ok(command) => command,
+        Err(e) => {
+            println!("Error in command line: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let source = match DataSource::from_args(&args) {
+        Ok(source) => source,
+        Err(e) => {
+            println!("Error in configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    let mut overview_file = File::create("graphs/index.html").unwrap();
+    if let Err(e) = run(&command, &args, &source) {
+        println!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
 
-    let incr_before_20200227 = 217;
-    let from: Date<Utc> = Utc.ymd(2020, 2, 27);
+// the data of the analysis window that the commands work on
+struct Analysis {
+    calendar: Calendar,
+    cases: CaseCube,
+    tests: JhuSeries,
+    hospitalizations: TimeSeries<Hospitalization>,
+    prevalences: TimeSeries<Prevalence>,
+    lcps: Vec<LcpsOccupancy>,
+    backfill: Option<Backfill>,
+}
 
-    let all_cases    = get_cases(Some(from));
-    let (mut dutch_tests, test_total) = get_tests(Some(from)).clone();
-    let all_hospitalizations = get_hospitalizations(Some(from));
-    let all_prevalences = get_prevalences(Some(from));
+// where report writes a graph: in index.html, only as a page of its own, or only with --backfill
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    Overview,
+    Page,
+    Backfill,
+}
 
-    // if totals data is not up to date, we need to add the last day
-    let total = incr_before_20200227 + all_cases.iter().fold(0, |acc, (_,cases)| acc + cases.len());
-    if dutch_tests.len() < all_cases.len() {
-        dutch_tests.insert(all_cases.keys().into_iter().last().unwrap().clone(), total - test_total);
-    }
+// what a graph is made from
+struct GraphInput<'a> {
+    name: &'a str,
+    analysis: &'a Analysis,
+    args: &'a [String],
+    source: &'a DataSource,
+    cohorts: &'a [Cohort],
+}
 
-    let delay = find_delay(&dutch_tests, &all_cases);
-    println!("delay: {:?}", delay);
+// None when the graph is left out, the reason is printed
+type GraphBuilder = fn(&GraphInput) -> Result<Option<GraphSpec>, CoronaDataError>;
+
+// Every graph, the names graph accepts and the order of index.html come from this table.
+const GRAPHS: &[(&str, Section, GraphBuilder)] = &[
+    ("active_cases", Section::Overview, |g| {
+        let windows = g.source.windows;
+        Ok(g.scale_factors().map(|factors| create_graph(&g.analysis.cases, &g.analysis.tests, g.cohorts,
+            GraphSpec::new("active_cases", "Approximate infectious persons", "Active cases")
+                .transform(Transform::Active(windows.active))
+                .transform(Transform::Divide(factors)))))
+    }),
+    ("new_cases", Section::Overview, |g| {
+        let windows = g.source.windows;
+        Ok(Some(create_graph(&g.analysis.cases, &g.analysis.tests, g.cohorts,
+            GraphSpec::new("new_cases", &format!("New cases ({} day average)", windows.average), "New cases")
+                .transform(Transform::Average(windows.average)))))
+    }),
+    ("growth_factor", Section::Overview, |g| {
+        let windows = g.source.windows;
+        Ok(Some(create_graph(&g.analysis.cases, &g.analysis.tests, g.cohorts,
+            GraphSpec::new("growth_factor", "Growth factor per age group", "Growth factor")
+                .div("growth")
+                .transform(Transform::Active(windows.active))
+                .transform(Transform::Growth)
+                .transform(Transform::Average(windows.growth)))))
+    }),
+    // ("growth_of_growth_factor", Section::Overview, |g|
+    //     Ok(Some(create_graph(&g.analysis.cases, &g.analysis.tests, g.cohorts,
+    //         GraphSpec::new("growth_of_growth_factor", "Growth of the Growth factor per age group", "Growth factor of the growth factor")
+    //             .div("growth_growth")
+    //             .transform(Transform::Active(10)).transform(Transform::Growth).transform(Transform::Average(5))
+    //             .transform(Transform::Growth).transform(Transform::Average(5)))))),
+    ("hospitalizations", Section::Overview, |g| Ok(Some(hospitalization_graph(&g.analysis.hospitalizations)))),
+    ("lcps", Section::Overview, |g| {
+        if !g.source.lcps_path().exists() {
+            println!("Warning: {} not found, download it with download --source lcps", g.source.lcps_path().display());
+            return Ok(None);
+        }
+        Ok(lcps_graph(&g.analysis.lcps, &g.analysis.hospitalizations))
+    }),
+    ("countries", Section::Overview, load_country_graph),
+    ("case_fatality_rate", Section::Overview, load_cfr_graph),
+    ("deaths_by_week", Section::Overview, |g| Ok(Some(deaths_by_week_graph(&g.analysis.cases)))),
+    ("provinces", Section::Overview, |g|
+        Ok(Some(group_graph(&g.analysis.cases, &GroupBy::province, "provinces", "New cases per province (7 day average)")))),
+    ("ggd_regions", Section::Overview, |g|
+        Ok(Some(group_graph(&g.analysis.cases, &GroupBy::municipal_health_service, "ggd_regions", "New cases per GGD region (7 day average)")))),
+    ("linreg", Section::Overview, |g|
+        Ok(g.scale_factors().map(|factors| trends(&g.analysis.cases, &g.analysis.hospitalizations, &factors, g.source.windows.active)))),
+    ("trends_of_trends", Section::Overview, |g|
+        Ok(g.scale_factors().map(|factors| trends_of_trends(&g.analysis.cases, &g.analysis.hospitalizations, &factors, g.source.windows.active)))),
+    ("factors", Section::Page, |g| Ok(g.scale_factors().map(|factors| prevalence_factor_graph(&factors)))),
+    ("completeness", Section::Backfill, |g| Ok(g.analysis.backfill.as_ref().map(completeness_graph))),
+    ("reporting_delay", Section::Backfill, |g| Ok(g.analysis.backfill.as_ref().map(reporting_delay_graph))),
+    ("backfill", Section::Backfill, |g| Ok(g.analysis.backfill.as_ref().map(backfill_growth_graph))),
+];
+
+fn graph_names() -> Vec<&'static str> {
+    GRAPHS.iter().map(|(name, _, _)| *name).collect()
+}
+
+fn graphs_in(section: Section) -> impl Iterator<Item = &'static (&'static str, Section, GraphBuilder)> {
+    GRAPHS.iter().filter(move |(_, graph_section, _)| *graph_section == section)
+}
 
-    write_header(&mut overview_file);
+impl GraphInput<'_> {
+    // the active cases are scaled by the prevalence, without a day that has both the graph is left out
+    fn scale_factors(&self) -> Option<Series> {
+        let factors = get_scale_factors(&self.analysis.cases, &self.analysis.prevalences, self.source.windows.active);
+        if factors.is_none() {
+            println!("Warning: {} left out, no day has both cases and a prevalence", self.name);
+        }
+        factors
+    }
+}
 
-    let calculate_active_cases = | cs: &Vec<f32>, factors: &Vec<f32> | {
-        let ac = active_cases(&cs, 10);
-        let mut res: Vec<f32> = vec![];
-        for (f, v) in factors.iter().zip(ac.iter()) {
-            res.push(v/f);
+fn run(command: &Command, args: &[String], source: &DataSource) -> Result<(), CoronaDataError> {
+    if *command == Command::Download {
+        for (id, e) in download_data(source, &get_download_sources(args)) {
+            println!("Warning: could not download {}, using the previous copy: {}", id, e);
         }
-        res
+        match archive_snapshot(source) {
+            Ok(Some(date)) => println!("archived the snapshot of {}", date),
+            Ok(None) => {}
+            Err(e) => println!("Warning: could not archive the snapshot: {}", e),
+        }
+        return Ok(());
+    }
+
+    let snapshot_date = get_snapshot_date(args);
+    let snapshot = match snapshot_date {
+        Some(date) => Some(snapshot_source(source, date)?),
+        None => None,
     };
-    create_graph(&all_cases, &dutch_tests, &all_prevalences, &calculate_active_cases, 10, "Approximate infectious persons", "Active cases", "graphs/active_cases.html", "active_cases", &mut overview_file);
+    // the archive is not part of a snapshot, it is read through the original source
+    let archive = source;
+    let source = snapshot.as_ref().unwrap_or(source);
+
+    let cohorts = get_cohorts(args);
+    let analysis = load_analysis(command, args, source, archive, snapshot_date)?;
+
+    match command {
+        Command::Download => {}
+        Command::Report => report(&analysis, args, source, &cohorts)?,
+        Command::Graph(name) => {
+            // from_args only accepts the names in GRAPHS
+            for graph in GRAPHS.iter().filter(|(graph, _, _)| graph == name) {
+                write_graph(graph, &analysis, args, source, &cohorts, &mut std::io::sink())?;
+            }
+        }
+        Command::Peaks => print_peaks(&find_peaks(&analysis.cases, &analysis.prevalences, source.windows.active)),
+        Command::Clusters => calculate_clusters(find_peaks(&analysis.cases, &analysis.prevalences, source.windows.active), &source.clusters_dir)?,
+        Command::Export => export(&analysis, source, &cohorts, args.contains(&"--weekly".to_string()))?,
+        Command::Summary => summary(&analysis, source, &cohorts),
+    }
+    Ok(())
+}
 
-    let calculate_new_cases = | cs: &Vec<f32>, _: &Vec<f32> | {
-        windowed_average(&cs, 3)
+fn load_analysis(command: &Command, args: &[String], source: &DataSource, archive: &DataSource, snapshot_date: Option<NaiveDate>) -> Result<Analysis, CoronaDataError> {
+    let data = load_data(source, Some(source.from), !args.contains(&"--no-cache".to_string()))?;
+    let data = match source.until {
+        Some(until) => data.until(until),
+        None => data,
     };
-    create_graph(&all_cases, &dutch_tests, &all_prevalences, &calculate_new_cases, 3, "New cases (3 day average)", "New cases", "graphs/new_cases.html", "new_cases", &mut overview_file);
 
-    let calculate_growth_factor = | cs: &Vec<f32>, _: &Vec<f32> | {
-        windowed_average( &growth_factor( &active_cases( &cs , 10)  ), 5)
+    // also when the tests come from the cache, which is not rebuilt for every run
+    log_corrections(&source.confirmed_path(), &Region::country("Netherlands"), &data.tests);
+    for (name, gaps) in [("tests", data.tests.daily.gaps()), ("hospitalizations", data.hospitalizations.gaps()), ("prevalences", data.prevalences.gaps())] {
+        if let (Some(first), Some(last)) = (gaps.first(), gaps.last()) {
+            println!("{}: {} day(s) missing between {} and {}", name, gaps.len(), first, last);
+        }
+    }
+
+    // every day of the window gets a count, also the days nothing was reported
+    let calendar = Calendar::new(source.from, data.cases.dates().last().copied().unwrap_or(source.from));
+    let cases = data.cases.with_calendar(&calendar);
+    let mut dutch_tests = data.tests.with_calendar(&calendar);
+
+    // if the JHU data is not up to date, its last day is estimated from the RIVM total (which
+    // includes the cases before the analysis window, as the cumulative JHU total does)
+    let total = cases.before + cases.total();
+    if !cases.is_empty() && dutch_tests.daily.last_date().is_none_or(|last| last < calendar.last) {
+        let date = calendar.last;
+        let estimate = total as i64 - dutch_tests.total;
+        // the RIVM total is below the JHU one, e.g. when JHU counted cases RIVM has since removed
+        if estimate < 0 {
+            println!("Warning: tests of {} not estimated, the RIVM total is {} below the JHU total", date, -estimate);
+        } else {
+            println!("tests of {} estimated from the RIVM total: {}", date, estimate);
+            dutch_tests.estimate(date, estimate);
+        }
+    }
+
+    // --nowcast corrects the most recent days for the cases that are not reported yet, using the
+    // reporting delays measured in the archived snapshots (also shown as graphs with --backfill),
+    // with --snapshot only the snapshots up to that day are used
+    let nowcast = args.contains(&"--nowcast".to_string());
+    let backfill_graph = matches!(command, Command::Graph(name) if graphs_in(Section::Backfill).any(|(graph, _, _)| graph == name));
+    let mut backfill = None;
+    if nowcast || backfill_graph || args.contains(&"--backfill".to_string()) {
+        let snapshots = Backfill::from_archive(archive, Some(source.from), snapshot_date)?;
+        if snapshots.len() < 2 {
+            println!("backfill: needs at least two archived snapshots, found {}", snapshots.len());
+        } else {
+            backfill = Some(snapshots);
+        }
+    }
+    let all_cases = match &backfill {
+        Some(backfill) if nowcast => cases.with_nowcast(&Nowcast::from_backfill(backfill)),
+        _ => cases,
     };
-    create_graph(&all_cases, &dutch_tests, &all_prevalences, &calculate_growth_factor, 10+5+1, "Growth factor per age group", "Growth factor", "graphs/growth_factor.html", "growth", &mut overview_file);
 
-    // let calculate_growth_of_growth_factor = | cs: &Vec<f32> | {
-    //     windowed_average(&growth_factor(&windowed_average( &growth_factor( &active_cases( &cs , 10)  ), 5)), 5)
-    // };
-    // create_graph(&all_cases, &dutch_tests, &calculate_growth_of_growth_factor, 10+5+1+5+1, "Growth of the Growth factor per age group", "Growth factor of the growth factor", "graphs/growth_of_growth_factor.html", "growth_growth", &mut overview_file);
+    Ok(Analysis {
+        calendar,
+        cases: all_cases,
+        tests: dutch_tests,
+        hospitalizations: data.hospitalizations,
+        prevalences: data.prevalences,
+        lcps: data.lcps,
+        backfill,
+    })
+}
 
-    hospitalization_graph(&all_hospitalizations, "hospitalizations", &mut overview_file);
+// every graph with graphs/index.html, the clusters and with --backfill the reporting delay graphs
+fn report(analysis: &Analysis, args: &[String], source: &DataSource, cohorts: &[Cohort]) -> Result<(), CoronaDataError> {
+    let delay = find_delay(&analysis.tests, &analysis.cases, source.windows.active);
+    println!("delay: {:?}", delay);
 
-    trends(&all_cases, &all_hospitalizations, &all_prevalences, "trends", &mut overview_file);
-    
-    trends_of_trends(&all_cases, &all_hospitalizations, &all_prevalences, "trendsoftrends", &mut overview_file);
+    let overview_path = source.output_path("index.html");
+    let mut overview_file = File::create(&overview_path).map_err(|e| CoronaDataError::io(&overview_path, e))?;
+    write_header(source, &mut overview_file)?;
+    for graph in graphs_in(Section::Overview) {
+        write_graph(graph, analysis, args, source, cohorts, &mut overview_file)?;
+    }
+    write_footer(source, &mut overview_file)?;
 
-    write_footer(&mut overview_file);
+    for graph in graphs_in(Section::Page) {
+        write_graph(graph, analysis, args, source, cohorts, &mut std::io::sink())?;
+    }
 
-    prevalence_factor_graph(&all_cases, &all_prevalences);
+    calculate_clusters(find_peaks(&analysis.cases, &analysis.prevalences, source.windows.active), &source.clusters_dir)?;
 
-    calculate_peaks( &all_cases, &all_prevalences);
+    if args.contains(&"--backfill".to_string()) {
+        for graph in graphs_in(Section::Backfill) {
+            write_graph(graph, analysis, args, source, cohorts, &mut std::io::sink())?;
+        }
+    }
+    Ok(())
 }
 
-fn create_graph(
-    all_cases: &BTreeMap<String, Vec<Case>>, 
-    dutch_tests: &BTreeMap<String, usize>, 
-    all_prevalences: &BTreeMap<String, Prevalence>,
-    calculation: &dyn Fn(&Vec<f32>, &Vec<f32>) -> Vec<f32>, 
-    filter_size_labels: usize,
-    title: &str,
-    y_axis_title: &str,
-    filename: &str,
-    div_name: &'static str,
-    overview_file: &mut File
-) {
-
-    let case_counts = | cs: &BTreeMap<String, Vec<Case>> | -> Vec<f32> {
-        cs.iter().map(|(_, cases)| cases.len() as f32 ).collect::<Vec<f32>>()
+// writes <output dir>/<name>.html, the graphs of index.html also add their div to overview_file
+fn write_graph(&(name, _, build): &(&str, Section, GraphBuilder), analysis: &Analysis, args: &[String], source: &DataSource, cohorts: &[Cohort], overview_file: &mut dyn Write) -> Result<(), CoronaDataError> {
+    let input = GraphInput { name, analysis, args, source, cohorts };
+    if let Some(graph) = build(&input)? {
+        graph.render(&source.output_dir, overview_file)?;
+    }
+    Ok(())
+}
+
+fn load_country_graph(g: &GraphInput) -> Result<Option<GraphSpec>, CoronaDataError> {
+    let source = g.source;
+    let regions = get_regions(g.args);
+    let confirmed = get_confirmed(source, Some(source.from), &regions)?.into_iter().map(|(region, series)| {
+        // the corrections of the Dutch cases are printed with the tests
+        if region != Region::country("Netherlands") {
+            log_corrections(&source.confirmed_path(), &region, &series);
+        }
+        let series = match source.until { Some(until) => series.until(until), None => series };
+        (region, series.with_calendar(&g.analysis.calendar))
+    }).collect::<BTreeMap<Region, JhuSeries>>();
+    for region in regions.iter().filter(|region| !confirmed.contains_key(region)) {
+        println!("Warning: {} is not in {}", region, source.confirmed_path().display());
+    }
+    Ok(country_graph(&confirmed, "New confirmed cases per country (7 day average)"))
+}
+
+fn load_cfr_graph(g: &GraphInput) -> Result<Option<GraphSpec>, CoronaDataError> {
+    let source = g.source;
+    if !source.deaths_path().exists() {
+        println!("Warning: {} not found, download it with download --source jhu-deaths", source.deaths_path().display());
+        return Ok(None);
+    }
+    let netherlands = [Region::country("Netherlands")];
+    let until = |series: JhuSeries| match source.until { Some(until) => series.until(until), None => series }.with_calendar(&g.analysis.calendar);
+    let deaths = get_deaths(source, Some(source.from), &netherlands)?.remove(&netherlands[0]).unwrap_or_default();
+    let recovered = if source.recovered_path().exists() {
+        get_recovered(source, Some(source.from), &netherlands)?.remove(&netherlands[0])
+    } else {
+        None
     };
+    let confirmed = get_confirmed(source, Some(source.from), &netherlands)?.remove(&netherlands[0]).unwrap_or_default();
+    log_corrections(&source.deaths_path(), &netherlands[0], &deaths);
+    if let Some(recovered) = &recovered {
+        log_corrections(&source.recovered_path(), &netherlands[0], recovered);
+    }
+    Ok(Some(cfr_graph(&g.analysis.cases, &until(confirmed), &until(deaths), recovered.map(until).as_ref())))
+}
 
-    let dutch_counts = | dt: &BTreeMap<String, usize> | -> Vec<f32> {
-        dt.iter().map(|(_,&v)| v as f32).collect::<Vec<f32>>()
+// the daily series of the analysis window as CSV: one row per day, one column per series
+fn export(analysis: &Analysis, source: &DataSource, cohorts: &[Cohort], weekly: bool) -> Result<(), CoronaDataError> {
+    let all_cases = &analysis.cases;
+    let mut columns: Vec<(String, Series)> = vec![("tests".to_string(), analysis.tests.series()), ("all".to_string(), all_cases.all())];
+    for group in AgeGroup::DECADES.iter() {
+        columns.push((group.as_str().to_string(), all_cases.series(&|key: &CubeKey| key.age_group == *group)));
+    }
+    columns.push(("deceased".to_string(), all_cases.series(&Filters::dead)));
+    for cohort in cohorts {
+        columns.push((cohort.name.clone(), all_cases.series(&|key: &CubeKey| cohort.expr.matches(key))));
+    }
+    columns.push(("ic".to_string(), analysis.hospitalizations.series(|h| h.ic_patients)));
+    columns.push(("ward".to_string(), analysis.hospitalizations.series(|h| h.rc_patients)));
+
+    // with --weekly every row is the sum of an ISO week, dated by its Monday, and its tests are
+    // estimated if one of its days is
+    let (path, dates, estimated, unit) = if weekly {
+        columns = columns.into_iter().map(|(name, series)| (name, series.weekly())).collect();
+        let weeks = all_cases.dates().into_iter().map(iso_week).collect::<BTreeSet<NaiveDate>>();
+        let estimated = analysis.tests.estimated.iter().map(|&date| iso_week(date)).collect::<BTreeSet<NaiveDate>>();
+        (source.output_path("weekly_series.csv"), weeks.into_iter().collect::<Vec<NaiveDate>>(), estimated, "weeks")
+    } else {
+        (source.output_path("series.csv"), all_cases.dates(), analysis.tests.estimated.clone(), "days")
     };
 
-    let set_cases: Vec<(Vec<f32>, &str)> = vec![
-        ( dutch_counts(dutch_tests)                                                 , "Tests"),
-        ( case_counts(&all_cases)                                                   , "All"), 
-        ( case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_0_9    ])), " 0-9 "), 
-        ( case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_10_19  ])), "10-19"),
-        ( case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_20_29  ])), "20-29"),
-        ( case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_30_39  ])), "30-39"),
-        ( case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_40_49  ])), "40-49"),
-        ( case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_50_59  ])), "50-59"),
-        ( case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_60_69  ])), "60-69"),
-        ( case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_70_79  ])), "70-79"),
-        ( case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_80_89  ])), "80-89"),
-        ( case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_90_plus])), "90-xx")
-    ];
+    let mut writer = csv::Writer::from_path(&path).map_err(|e| CoronaDataError::csv(&path, e))?;
+    let mut header = vec!["date".to_string(), "tests".to_string(), "tests_estimated".to_string()];
+    header.extend(columns.iter().skip(1).map(|(name, _)| name.clone()));
+    writer.write_record(&header).map_err(|e| CoronaDataError::csv(&path, e))?;
+
+    // days without a value are left empty
+    let value = |value: Option<f32>| value.filter(|value| !value.is_nan()).map_or(String::new(), |value| value.to_string());
+    for date in &dates {
+        let mut record = vec![
+            date.format("%Y-%m-%d").to_string(),
+            value(columns[0].1.get(*date)),
+            estimated.contains(date).to_string(),
+        ];
+        record.extend(columns.iter().skip(1).map(|(_, series)| value(series.get(*date))));
+        writer.write_record(&record).map_err(|e| CoronaDataError::csv(&path, e))?;
+    }
+    writer.flush().map_err(|e| CoronaDataError::io(&path, e))?;
+    println!("{}: {} {}", path.display(), dates.len(), unit);
+    Ok(())
+}
 
-    let factors = get_scale_factors(&all_cases, &all_prevalences).iter().skip(0).map(|&v| v).collect::<Vec<f32>>();
+// the figures of the last day of the analysis window
+fn summary(analysis: &Analysis, source: &DataSource, cohorts: &[Cohort]) {
+    let all_cases = &analysis.cases;
+    let windows = source.windows;
+    let dates = all_cases.dates();
+    let (first, last) = match (dates.first(), dates.last()) {
+        (Some(first), Some(last)) => (*first, *last),
+        _ => {
+            println!("no cases between {} and {}", source.from, source.until.map_or("now".to_string(), |until| until.to_string()));
+            return;
+        }
+    };
+    println!("{} to {}: {} days, {} cases ({} before {})", first, last, dates.len(), all_cases.total(), all_cases.before, source.from);
 
-    let labels = all_cases.iter().skip(filter_size_labels).map(|(name,_)| {
-        let mut dashed_name = name.clone();
-        dashed_name.insert(6,'-',);
-        dashed_name.insert(4,'-',);
-        return dashed_name;
-    }).collect::<Vec<String>>();
+    let mut set_cases: Vec<(String, Series)> = vec![("All".to_string(), all_cases.all())];
+    for group in AgeGroup::DECADES.iter() {
+        set_cases.push((group.as_str().to_string(), all_cases.series(&|key: &CubeKey| key.age_group == *group)));
+    }
+    for cohort in cohorts {
+        set_cases.push((cohort.name.clone(), all_cases.series(&|key: &CubeKey| cohort.expr.matches(key))));
+    }
+
+    println!("{:>12} {:>8} {:>12} {:>8} {:>8}", "", "new", format!("{} day avg", windows.average), "active", "growth");
+    for (name, cases) in &set_cases {
+        // the transforms could leave nothing in a short window
+        let last_value = |series: Series| series.last().map_or(f32::NAN, |(_, value)| value);
+        println!("{:>12} {:>8} {:>12.1} {:>8} {:>8.3}",
+            name,
+            last_value(cases.clone()),
+            last_value(cases.rolling_mean(windows.average)),
+            last_value(cases.active(windows.active)),
+            last_value(cases.active(windows.active).ratio().rolling_mean(windows.growth)));
+    }
+
+    match analysis.tests.daily.get(last) {
+        Some(tests) if analysis.tests.estimated.contains(&last) => println!("tests: {} (estimated), {} in total", tests, analysis.tests.total),
+        Some(tests) => println!("tests: {}, {} in total", tests, analysis.tests.total),
+        None => println!("tests: not reported, {} in total", analysis.tests.total),
+    }
+    if let Some((date, hospitalization)) = analysis.hospitalizations.iter().rev().find(|(date, _)| *date <= last) {
+        println!("hospitalized on {}: {} IC, {} ward", date, hospitalization.ic_patients, hospitalization.rc_patients);
+    }
+    println!("delay: {:?}", find_delay(&analysis.tests, all_cases, windows.active));
+}
+
+// --source rivm-cases,nice-ic selects the sources to download, all of them by default
+fn get_download_sources(args: &[String]) -> Vec<&'static Source> {
+    let ids = match args.windows(2).rev().find(|w| w[0] == "--source") {
+        Some(w) => w[1].split(',').map(|id| id.trim()).collect::<Vec<&str>>(),
+        None => return SOURCES.iter().collect(),
+    };
+    ids.iter().map(|id| {
+        find_source(id).unwrap_or_else(|| {
+            let known = SOURCES.iter().map(|source| source.id).collect::<Vec<&str>>();
+            println!("Unknown source '{}', expected one of: {}", id, known.join(", "));
+            std::process::exit(1);
+        })
+    }).collect()
+}
+
+// --snapshot 2020-12-26 reads the archived files of that day instead of the current ones
+fn get_snapshot_date(args: &[String]) -> Option<NaiveDate> {
+    let value = &args.windows(2).rev().find(|w| w[0] == "--snapshot")?[1];
+    match parse_snapshot_date(value) {
+        Ok(date) => Some(date),
+        Err(e) => {
+            println!("Error in snapshot {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// --countries Netherlands,Belgium,Germany/Bavaria selects the regions of the JHU data to compare,
+// names with a comma are quoted: --countries 'Netherlands,"Korea, South"'
+fn get_regions(args: &[String]) -> Vec<Region> {
+    let value = args.windows(2).rev().find(|w| w[0] == "--countries").map_or("Netherlands,Belgium,Germany", |w| w[1].as_str());
+    match parse_regions(value) {
+        Ok(regions) => regions,
+        Err(e) => {
+            println!("Error in countries '{}': {}", value, e);
+            std::process::exit(1);
+        }
+    }
+}
 
-    let y_data = set_cases.iter().map(|sc| {
-        (sc.1.to_string(), calculation(&sc.0, &factors))
-    }).collect::<Vec<(String, Vec<f32>)>>();
+// cohorts are given as --cohort "<expression>" or as a file with one expression per line: --cohort-file <file>
+fn get_cohorts(args: &[String]) -> Vec<Cohort> {
+    let mut cohorts: Vec<Cohort> = vec![];
+    for (flag, value) in args.iter().zip(args.iter().skip(1)) {
+        let parsed = match flag.as_str() {
+            "--cohort"      => Cohort::parse(value).map(|cohort| vec![cohort]),
+            "--cohort-file" => read_cohort_file(value),
+            _ => continue,
+        };
+        match parsed {
+            Ok(mut cs) => cohorts.append(&mut cs),
+            Err(e) => {
+                println!("Error in cohort '{}': {}", value, e);
+                std::process::exit(1);
+            }
+        }
+    }
+    cohorts
+}
 
-    let begin = labels.iter().rev().skip(30).next().unwrap();
-    let end = labels.iter().last().unwrap();
+// adds the traces of the tests, all cases, the age groups and the cohorts to the graph, with the
+// estimated tests days marked and the nowcast band if there is one
+fn create_graph(
+    all_cases: &CaseCube, 
+    dutch_tests: &JhuSeries, 
+    cohorts: &[Cohort],
+    graph: GraphSpec
+) -> GraphSpec {
+
+    let mut set_cases: Vec<(Series, &str)> = vec![
+        ( dutch_tests.series()                         , "Tests"),
+        ( all_cases.all()                              , "All"), 
+        ( all_cases.series(&Filters::age_group_0_9)    , " 0-9 "), 
+        ( all_cases.series(&Filters::age_group_10_19)  , "10-19"),
+        ( all_cases.series(&Filters::age_group_20_29)  , "20-29"),
+        ( all_cases.series(&Filters::age_group_30_39)  , "30-39"),
+        ( all_cases.series(&Filters::age_group_40_49)  , "40-49"),
+        ( all_cases.series(&Filters::age_group_50_59)  , "50-59"),
+        ( all_cases.series(&Filters::age_group_60_69)  , "60-69"),
+        ( all_cases.series(&Filters::age_group_70_79)  , "70-79"),
+        ( all_cases.series(&Filters::age_group_80_89)  , "80-89"),
+        ( all_cases.series(&Filters::age_group_90_plus), "90-xx")
+    ];
 
-    let layout = Layout::new().bar_mode(BarMode::Group)
-        .title(Title::new(title).font(Font::new().color(NamedColor::Black).size(24).family("Droid Serif")))
-        .x_axis(Axis::new().type_(AxisType::Date).title(Title::new("Day").font(Font::new().color(NamedColor::Black).size(12).family("Droid Serif"))).range(vec![begin,end]))
-        .y_axis(Axis::new().title(Title::new(y_axis_title).font(Font::new().color(NamedColor::Black).size(12).family("Droid Serif"))));
+    for cohort in cohorts {
+        set_cases.push(( all_cases.series(&|c: &CubeKey| cohort.expr.matches(c)), cohort.name.as_str() ));
+    }
 
-    let mut plot = Plot::new();
-    y_data.iter().for_each(|(name, data)| {
-        plot.add_trace( Scatter::new( labels.clone(), data.clone() ).name(name) )
-    });
-    plot.set_layout(layout);
+    // the days of the tests that are estimated are marked, they are not data
+    let tests = graph.apply(&set_cases[0].0);
+    let (estimated_dates, estimated_values): (Vec<NaiveDate>, Vec<f32>) = dutch_tests.estimated.iter()
+        .filter_map(|&date| tests.get(date).map(|value| (date, value)))
+        .unzip();
 
-    plot.to_html(filename);
-    let html = plot.to_inline_html(Some(div_name));
-    overview_file.write_all(html.as_bytes());
-    overview_file.write_all(b"\n");
+    let mut graph = graph.traces(set_cases.into_iter().map(|(cases, name)| Trace::new(name, cases)));
+    if !estimated_dates.is_empty() {
+        graph = graph.trace(Trace::raw("Tests (estimated)", Series::new(estimated_dates, estimated_values)).markers());
+    }
+    if let Some((low, high)) = all_cases.series_band(&|_| true) {
+        graph = graph
+            .trace(Trace::new("All (nowcast low)", low).dotted())
+            .trace(Trace::new("All (nowcast high)", high).dotted().fill());
+    }
+    graph
 }
 
 
-pub fn write_header(file: &mut File) {
-    let header = File::open("template/header.html").unwrap();
+pub fn write_header(source: &DataSource, file: &mut File) -> Result<(), CoronaDataError> {
+    let path = source.template_path("header.html");
+    let overview_path = source.output_path("index.html");
+    let header = File::open(&path).map_err(|e| CoronaDataError::io(&path, e))?;
     let reader = BufReader::new(header);
     for line in reader.lines() {
-        file.write_all(line.unwrap().as_bytes());
+        file.write_all(line.map_err(|e| CoronaDataError::io(&path, e))?.as_bytes()).map_err(|e| CoronaDataError::io(&overview_path, e))?;
         // println!("{}", line.unwrap());
     }
+    Ok(())
 }
 
-pub fn write_footer(file: &mut File) {
-    let header = File::open("template/footer.html").unwrap();
+pub fn write_footer(source: &DataSource, file: &mut File) -> Result<(), CoronaDataError> {
+    let path = source.template_path("footer.html");
+    let overview_path = source.output_path("index.html");
+    let header = File::open(&path).map_err(|e| CoronaDataError::io(&path, e))?;
     let reader = BufReader::new(header);
     for line in reader.lines() {
-        file.write_all(line.unwrap().as_bytes());
+        file.write_all(line.map_err(|e| CoronaDataError::io(&path, e))?.as_bytes()).map_err(|e| CoronaDataError::io(&overview_path, e))?;
         // println!("{}", line.unwrap());
     }
+    Ok(())
 }
 
 
-pub fn trends(
-    all_cases: &BTreeMap<String, Vec<Case>>, 
-    all_hospitalizations: &BTreeMap<String, Hospitalization>, 
-    all_prevalences: &BTreeMap<String, Prevalence>,
-    div_name: &'static str, 
-    overview_file: &mut File
-) {
-    let case_counts = | cs: &BTreeMap<String, Vec<Case>> | -> Vec<f32> {
-        cs.iter().map(|(_, cases)| cases.len() as f32 ).collect::<Vec<f32>>()
-    };
-
-    let last_case_date: String = all_cases.iter().last().unwrap().0.clone();
-
-    let set_cases: Vec<(Vec<f32>, &str)> = vec![
-        ( active_cases(&case_counts(&all_cases)                                                   ,10), "All"), 
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_0_9    ])),10), " 0-9 "), 
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_10_19  ])),10), "10-19"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_20_29  ])),10), "20-29"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_30_39  ])),10), "30-39"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_40_49  ])),10), "40-49"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_50_59  ])),10), "50-59"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_60_69  ])),10), "60-69"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_70_79  ])),10), "70-79"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_80_89  ])),10), "80-89"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_90_plus])),10), "90-xx"),
-        ( all_hospitalizations.iter().filter(|(name,_)| (*name).cmp(&last_case_date) != std::cmp::Ordering::Greater ).map(|(_,h)| h.ic_patients as f32).collect::<Vec<f32>>().clone() , "IC"),
-        ( all_hospitalizations.iter().filter(|(name,_)| (*name).cmp(&last_case_date) != std::cmp::Ordering::Greater ).map(|(_,h)| h.rc_patients as f32).collect::<Vec<f32>>().clone() , "RC"),
+// the active cases per age group and the patients in care, divided by the prevalence factor of
+// the same day, the days after the last case are left out
+fn trend_sets(
+    all_cases: &CaseCube,
+    all_hospitalizations: &TimeSeries<Hospitalization>,
+    factors: &Series,
+    active_window: usize
+) -> Vec<(Series, &'static str)> {
+
+    let set_cases: Vec<(Series, &str)> = vec![
+        ( all_cases.all().active(active_window), "All"), 
+        ( all_cases.series(&Filters::age_group_0_9).active(active_window), " 0-9 "), 
+        ( all_cases.series(&Filters::age_group_10_19).active(active_window), "10-19"),
+        ( all_cases.series(&Filters::age_group_20_29).active(active_window), "20-29"),
+        ( all_cases.series(&Filters::age_group_30_39).active(active_window), "30-39"),
+        ( all_cases.series(&Filters::age_group_40_49).active(active_window), "40-49"),
+        ( all_cases.series(&Filters::age_group_50_59).active(active_window), "50-59"),
+        ( all_cases.series(&Filters::age_group_60_69).active(active_window), "60-69"),
+        ( all_cases.series(&Filters::age_group_70_79).active(active_window), "70-79"),
+        ( all_cases.series(&Filters::age_group_80_89).active(active_window), "80-89"),
+        ( all_cases.series(&Filters::age_group_90_plus).active(active_window), "90-xx"),
+        ( all_hospitalizations.series(|h| h.ic_patients), "IC"),
+        ( all_hospitalizations.series(|h| h.rc_patients), "RC"),
     ];
 
-    let factors = get_scale_factors(&all_cases, &all_prevalences);
+    set_cases.into_iter().map(|(series, name)| (series.divide(factors), name)).collect()
+}
+
+// the slope of the linear regression of the 7 days up to and including the date
+fn trend_at(series: &Series, date: NaiveDate) -> f32 {
+    let first = date - Duration::days(6);
+    let week = series.between(first, date);
+    let points = week.dates().iter().zip(week.values()).map(|(day, &v)| ((*day - first).num_days() as f32, v)).collect::<Vec<(f32,f32)>>();
+    linear_regression_of(&points).map_or(f32::NAN, |lr: (f32, f32)| lr.0)
+}
+
+fn trend_series(series: &Series, dates: &[NaiveDate]) -> Series {
+    Series::new(dates.to_vec(), dates.iter().map(|&date| trend_at(series, date)).collect())
+}
 
+// the days the trends are calculated for, the last day is left out as it is not complete yet
+fn trend_dates(all_cases: &CaseCube) -> Vec<NaiveDate> {
     let start_day = 1;
     let max_days_back = 224;
-    let mut results: BTreeMap<String, Vec<(f32,f32)>> = BTreeMap::new();
-
-    for days_back in (start_day..max_days_back).rev() {
-        let last_seven = set_cases.iter().map(|cases| 
-            (
-                cases.1.to_string(), 
-                cases.0.iter().rev().skip(days_back).take(7).rev().enumerate().map(|(index, &v)| (index as f32,v / factors[factors.len()-(days_back+6-index)-1]) ).collect::<Vec<(f32,f32)>>()
-            ) 
-        ).collect::<Vec<(String, Vec<(f32,f32)>)>>();
-
-        for set in &last_seven {
-            // let last_value = set.1.iter().rev().next().unwrap().1;
-            let lr: (f32, f32) = linear_regression_of(&set.1).unwrap();
-            results.entry(set.0.clone()).or_insert(vec![]).push((0.0 - days_back as f32,lr.0));
-            // results.entry(set.0.clone()).or_insert(vec![]).push((0.0 - days_back as f32,lr.0 / last_value));
-        }
-    }
+    all_cases.dates().into_iter().rev().skip(start_day).take(max_days_back-start_day).rev().collect()
+}
 
-    let labels = all_cases.iter().rev().skip(start_day).take(max_days_back-start_day).rev().map(|(name,_)| {
-        let mut dashed_name = name.clone();
-        dashed_name.insert(6,'-',);
-        dashed_name.insert(4,'-',);
-        return dashed_name;
-    }).collect::<Vec<String>>();
+pub fn trends(
+    all_cases: &CaseCube, 
+    all_hospitalizations: &TimeSeries<Hospitalization>, 
+    factors: &Series,
+    active_window: usize
+) -> GraphSpec {
+    let dates = trend_dates(all_cases);
+
+    GraphSpec::new("linreg", "Rel. change in active cases (7 day lin.reg.)", "Increase/decrease")
+        .div("trends")
+        .days_shown(7)
+        .traces(trend_sets(all_cases, all_hospitalizations, factors, active_window).iter()
+            .map(|(series, name)| Trace::new(name, trend_series(series, &dates))))
+}
 
-    let begin = labels.iter().rev().skip(7).next().unwrap();
-    let end = labels.iter().last().unwrap();
+fn hospitalization_graph(all_hospitalizations: &TimeSeries<Hospitalization>) -> GraphSpec {
+    GraphSpec::new("hospitalizations", "Hospitalizations per day", "Patients in care")
+        .transform(Transform::Average(3))
+        .trace(Trace::new("IC", all_hospitalizations.series(|h| h.ic_patients)))
+        .trace(Trace::new("RC", all_hospitalizations.series(|h| h.rc_patients)))
+}
 
-    let y_data = set_cases.iter().map(|sc| {
-        (sc.1.to_string(), results[sc.1].iter().map(|(_,v)| *v).collect::<Vec<f32>>() )
-    }).collect::<Vec<(String, Vec<f32>)>>();
+// the beds occupied according to LCPS against the NICE counts, for the days both have
+fn lcps_graph(lcps_occupancy: &[LcpsOccupancy], all_hospitalizations: &TimeSeries<Hospitalization>) -> Option<GraphSpec> {
+    let days = lcps_occupancy.iter().filter_map(|occupancy| {
+        all_hospitalizations.get(occupancy.date).map(|hospitalization| (occupancy, hospitalization))
+    }).collect::<Vec<(&LcpsOccupancy, &Hospitalization)>>();
+    if days.is_empty() {
+        println!("lcps: no days in common with the NICE data");
+        return None;
+    }
 
-    let layout = Layout::new().bar_mode(BarMode::Group)
-        .title(Title::new("Rel. change in active cases (7 day lin.reg.)").font(Font::new().color(NamedColor::Black).size(24).family("Droid Serif")))
-        .x_axis(Axis::new().type_(AxisType::Date).title(Title::new("Day").font(Font::new().color(NamedColor::Black).size(12).family("Droid Serif"))).range(vec![begin,end]))
-        .y_axis(Axis::new().title(Title::new("Increase/decrease").font(Font::new().color(NamedColor::Black).size(12).family("Droid Serif"))));
+    let dates = days.iter().map(|(occupancy, _)| occupancy.date).collect::<Vec<NaiveDate>>();
+
+    // days without a value in the LCPS file are left out of the lines
+    let beds = |value: Option<usize>| value.map_or(f32::NAN, |value| value as f32);
+    let difference = |nice: f32, lcps: Option<usize>| lcps.map_or(f32::NAN, |lcps| nice - lcps as f32);
+    let y_data = [
+        ("IC (NICE)".to_string(),              days.iter().map(|(_, h)| h.ic_patients).collect::<Vec<f32>>()),
+        ("IC (LCPS)".to_string(),              days.iter().map(|(o, _)| beds(o.ic_beds)).collect::<Vec<f32>>()),
+        ("IC (NICE - LCPS)".to_string(),       days.iter().map(|(o, h)| difference(h.ic_patients, o.ic_beds)).collect::<Vec<f32>>()),
+        ("Ward (NICE)".to_string(),            days.iter().map(|(_, h)| h.rc_patients).collect::<Vec<f32>>()),
+        ("Ward (LCPS)".to_string(),            days.iter().map(|(o, _)| beds(o.ward_beds)).collect::<Vec<f32>>()),
+        ("Ward (NICE - LCPS)".to_string(),     days.iter().map(|(o, h)| difference(h.rc_patients, o.ward_beds)).collect::<Vec<f32>>()),
+    ];
 
-    let mut plot = Plot::new();
-    y_data.iter().for_each(|(name, data)| {
-        plot.add_trace( Scatter::new( labels.clone(), data.clone() ).name(name) )
-    });
-    plot.set_layout(layout);
+    Some(GraphSpec::new("lcps", "LCPS occupancy vs NICE", "Patients")
+        .traces(y_data.iter().map(|(name, data)| Trace::new(name, Series::new(dates.clone(), data.clone())))))
+}
 
-    plot.to_html("graphs/linreg.html");
-    let html = plot.to_inline_html(Some(div_name));
-    overview_file.write_all(html.as_bytes());
-    overview_file.write_all(b"\n");
+fn country_graph(
+    confirmed: &BTreeMap<Region, JhuSeries>,
+    title: &str
+) -> Option<GraphSpec> {
+    let dates = confirmed.values().flat_map(|series| series.daily.dates()).collect::<BTreeSet<NaiveDate>>();
+    if dates.len() < 8 {
+        return None;
+    }
 
+    Some(GraphSpec::new("countries", title, "New cases")
+        .transform(Transform::Average(7))
+        .traces(confirmed.iter().map(|(region, series)| Trace::new(&region.to_string(), series.series()))))
 }
 
-fn hospitalization_graph(all_hospitalizations: &BTreeMap<String, Hospitalization>, div_name: &'static str, overview_file: &mut File) {
-    let labels = all_hospitalizations.iter().skip(5).map(|(name,_)| {
-        let mut dashed_name = name.clone();
-        dashed_name.insert(6,'-',);
-        dashed_name.insert(4,'-',);
-        return dashed_name;
-    }).collect::<Vec<String>>();
-
-    let y_data = vec![
-        ("IC".to_string(), windowed_average(&all_hospitalizations.iter().map(|(_,h)| h.ic_patients as f32 ).collect::<Vec<f32>>(), 3)),
-        ("RC".to_string(),   windowed_average(&all_hospitalizations.iter().map(|(_,h)| h.rc_patients as f32 ).collect::<Vec<f32>>(), 3))
+// the case fatality rate in the Netherlands according to JHU and to RIVM (by Date_statistics),
+// with the deaths per day of both on the second axis
+fn cfr_graph(
+    all_cases: &CaseCube,
+    confirmed: &JhuSeries,
+    deaths: &JhuSeries,
+    recovered: Option<&JhuSeries>
+) -> GraphSpec {
+    let rate = |dead: &Series, total: &Series| dead.zip_with(total, |d, t| if t > 0.0 { 100.0 * d / t } else { f32::NAN });
+
+    let rivm_deaths = all_cases.series(&Filters::dead);
+    let jhu_deaths = deaths.series();
+    let mut y_data = vec![
+        ("CFR JHU".to_string(),  rate(&jhu_deaths.cumulative(), &confirmed.series().cumulative())),
+        ("CFR RIVM".to_string(), rate(&rivm_deaths.cumulative(), &all_cases.all().cumulative())),
     ];
+    if let Some(recovered) = recovered.filter(|recovered| recovered.total > 0) {
+        let resolved = jhu_deaths.cumulative().zip_with(&recovered.series().cumulative(), |d, r| d + r);
+        y_data.push(("CFR JHU (deaths / (deaths + recovered))".to_string(), rate(&jhu_deaths.cumulative(), &resolved)));
+    }
 
-    let begin = labels.iter().rev().skip(30).next().unwrap();
-    let end = labels.iter().last().unwrap();
-
-    let layout = Layout::new().bar_mode(BarMode::Group)
-        .title(Title::new("Hospitalizations per day").font(Font::new().color(NamedColor::Black).size(24).family("Droid Serif")))
-        .x_axis(Axis::new().type_(AxisType::Date).title(Title::new("Day").font(Font::new().color(NamedColor::Black).size(12).family("Droid Serif"))).range(vec![begin,end]))
-        .y_axis(Axis::new().title(Title::new("Patients in care").font(Font::new().color(NamedColor::Black).size(12).family("Droid Serif"))));
-
-    let mut plot = Plot::new();
-    y_data.iter().for_each(|(name, data)| {
-        plot.add_trace( Scatter::new( labels.clone(), data.clone() ).name(name) )
-    });
-    plot.set_layout(layout);
-
-    plot.to_html("graphs/hospitalizations.html");
-    let html = plot.to_inline_html(Some(div_name));
-    overview_file.write_all(html.as_bytes());
-    overview_file.write_all(b"\n");
+    GraphSpec::new("case_fatality_rate", "Case fatality rate", "Deaths per 100 cases")
+        .div("cfr")
+        .y2_title("Deaths per day (7 day average)")
+        .traces(y_data.into_iter().map(|(name, data)| Trace::new(&name, data)))
+        .trace(Trace::new("Deaths JHU", jhu_deaths.rolling_mean(7)).y2().dotted())
+        .trace(Trace::new("Deceased RIVM", rivm_deaths.rolling_mean(7)).y2().dotted())
+}
 
+fn group_graph(
+    all_cases: &CaseCube,
+    key: &dyn Fn(&CubeKey) -> String,
+    name: &'static str,
+    title: &str
+) -> GraphSpec {
+    GraphSpec::new(name, title, "New cases")
+        .transform(Transform::Average(7))
+        .traces(all_cases.group_series(key).into_iter().map(|(name, cases)| Trace::new(&name, cases)))
 }
 
-fn find_delay(dutch_tests: &BTreeMap<String, usize>, all_cases: &BTreeMap<String, Vec<Case>>) -> (f32, f32) {
+fn find_delay(dutch_tests: &JhuSeries, all_cases: &CaseCube, active_window: usize) -> (f32, f32) {
     let in_between = |v: f32, b: f32, e: f32| { (b <= v && v <= e) || (e <= v && v <= b) };
-    let case_counts = | cs: &BTreeMap<String, Vec<Case>> | -> Vec<f32> {
-        cs.iter().map(|(_, cases)| cases.len() as f32 ).collect::<Vec<f32>>()
-    };
-
-    let all_counts = case_counts(all_cases);
-    let all_tests = dutch_tests.iter().map(|(_,&c)| c as f32 ).collect::<Vec<f32>>();
 
-    let diffs = all_counts.iter().enumerate().map(|(index, &value)|  {
-        let b: usize = index+1;
-        let e: usize = &all_tests.len()-1;
-        for index2 in b..e {
-            if in_between(value, all_tests[index2], all_tests[index2+1]) {
-                return (index2-index) as f32;
-            }
+    // NaN when there are fewer than two days of tests, e.g. with a short window
+    let mean_delay = |all_counts: &[f32], all_tests: &[f32]| {
+        if all_tests.len() < 2 {
+            return f32::NAN;
         }
-        0f32
-    }).filter(|&v| v > 0.0f32).collect::<Vec<f32>>();
-
-    let cases_delay = diffs.iter().sum::<f32>() / diffs.len() as f32;
-
-    let all_counts = active_cases(&case_counts(all_cases), 10);
-    let all_tests = active_cases(&dutch_tests.iter().map(|(_,&c)| c as f32 ).collect::<Vec<f32>>(), 10);
-
-    let diffs = all_counts.iter().enumerate().map(|(index, &value)|  {
-        let b: usize = index+1;
-        let e: usize = &all_tests.len()-1;
-        for index2 in b..e {
-            if in_between(value, all_tests[index2], all_tests[index2+1]) {
-                return (index2-index) as f32;
+        let diffs = all_counts.iter().enumerate().map(|(index, &value)|  {
+            let b: usize = index+1;
+            let e: usize = all_tests.len()-1;
+            for index2 in b..e {
+                if in_between(value, all_tests[index2], all_tests[index2+1]) {
+                    return (index2-index) as f32;
+                }
             }
-        }
-        0f32
-    }).filter(|&v| v > 0.0f32).collect::<Vec<f32>>();
+            0f32
+        }).filter(|&v| v > 0.0f32).collect::<Vec<f32>>();
+        diffs.iter().sum::<f32>() / diffs.len() as f32
+    };
 
-    let infectious_delay = diffs.iter().sum::<f32>() / diffs.len() as f32;
+    let cases_delay = mean_delay(all_cases.all().values(), dutch_tests.series().values());
+    let infectious_delay = mean_delay(
+        all_cases.all().active(active_window).values(),
+        dutch_tests.series().active(active_window).values());
 
     (cases_delay, infectious_delay)
-    
 }
 
 
@@ -329,203 +755,163 @@ fn find_delay(dutch_tests: &BTreeMap<String, usize>, all_cases: &BTreeMap<String
 
 
 pub fn trends_of_trends(
-    all_cases: &BTreeMap<String, Vec<Case>>, 
-    all_hospitalizations: &BTreeMap<String, Hospitalization>, 
-    all_prevalences: &BTreeMap<String, Prevalence>,
-    div_name: &'static str, 
-    overview_file: &mut File
-) {
-    let case_counts = | cs: &BTreeMap<String, Vec<Case>> | -> Vec<f32> {
-        cs.iter().map(|(_, cases)| cases.len() as f32 ).collect::<Vec<f32>>()
-    };
+    all_cases: &CaseCube, 
+    all_hospitalizations: &TimeSeries<Hospitalization>, 
+    factors: &Series,
+    active_window: usize
+) -> GraphSpec {
+    let dates = trend_dates(all_cases);
+    // the first days that have a full week of trends
+    let trend_of_trend_dates = dates.iter().skip(6).copied().collect::<Vec<NaiveDate>>();
+
+    GraphSpec::new("trends_of_trends", "Change of the change in active cases (7 day lin.reg.)", "Increase/decrease")
+        .div("trendsoftrends")
+        .days_shown(7)
+        .traces(trend_sets(all_cases, all_hospitalizations, factors, active_window).iter()
+            .map(|(series, name)| Trace::new(name, trend_series(&trend_series(series, &dates), &trend_of_trend_dates))))
+}
 
-    let last_case_date: String = all_cases.iter().last().unwrap().0.clone();
-
-    let set_cases: Vec<(Vec<f32>, &str)> = vec![
-        ( active_cases(&case_counts(&all_cases)                                                   ,10), "All"), 
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_0_9    ])),10), " 0-9 "), 
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_10_19  ])),10), "10-19"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_20_29  ])),10), "20-29"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_30_39  ])),10), "30-39"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_40_49  ])),10), "40-49"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_50_59  ])),10), "50-59"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_60_69  ])),10), "60-69"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_70_79  ])),10), "70-79"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_80_89  ])),10), "80-89"),
-        ( active_cases(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_90_plus])),10), "90-xx"),
-        ( all_hospitalizations.iter().filter(|(name,_)| (*name).cmp(&last_case_date) != std::cmp::Ordering::Greater ).map(|(_,h)| h.ic_patients as f32).collect::<Vec<f32>>().clone() , "IC"),
-        ( all_hospitalizations.iter().filter(|(name,_)| (*name).cmp(&last_case_date) != std::cmp::Ordering::Greater ).map(|(_,h)| h.rc_patients as f32).collect::<Vec<f32>>().clone() , "RC"),
-    ];
+// none when no day has both cases and a prevalence, e.g. with a --from after the last prevalence
+pub fn get_scale_factors(all_cases: &CaseCube, all_prevalences: &TimeSeries<Prevalence>, active_window: usize) -> Option<Series> {
+    let set_cases = all_cases.all().active(active_window);
+    let factors = TimeSeries::from_series(&set_cases).intersection(all_prevalences)
+        .map(|(cases, prev)| cases / ((prev.prev_up + prev.prev_low) as f32 / 2.0f32));
 
-    let factors = get_scale_factors(&all_cases, &all_prevalences);
+    // the prevalence lags behind, the last factor is used for the days after it
+    let (last_date, &last_value) = factors.iter().next_back()?;
+    Some(factors.reindex(set_cases.dates().iter().copied(), |date| if date > last_date { last_value } else { f32::NAN })
+        .series(|&v| v))
+}
 
-    let start_day = 1;
-    let max_days_back = 224;
-    // let max_days_back = set_cases[0].0.len()-7;
-    let mut trends: BTreeMap<String, Vec<(f32,f32)>> = BTreeMap::new();
-    let mut trends_of_trends: BTreeMap<String, Vec<(f32,f32)>> = BTreeMap::new();
-
-    set_cases.iter().skip(start_day).for_each(|(_,name)| { trends.insert(name.to_string(), vec![]); trends_of_trends.insert(name.to_string(), vec![]); });
-
-    for days_back in (start_day..max_days_back).rev() {
-        // let last_seven = set_cases.iter().map(|cases| (cases.1.to_string(), cases.0.iter().rev().skip(days_back).take(7).rev().enumerate().map(|(index, &v)| (index as f32,v) ).collect::<Vec<(f32,f32)>>()) ).collect::<Vec<(String, Vec<(f32,f32)>)>>();
-        let last_seven = set_cases.iter().map(|cases| 
-            (
-                cases.1.to_string(), 
-                cases.0.iter().rev().skip(days_back).take(7).rev().enumerate().map(|(index, &v)| (index as f32,v / factors[factors.len()-(days_back+6-index)-1]) ).collect::<Vec<(f32,f32)>>()
-            ) 
-        ).collect::<Vec<(String, Vec<(f32,f32)>)>>();
-
-        for set in &last_seven {
-            // let last_value = set.1.iter().rev().next().unwrap().1;
-            let lr: (f32, f32) = linear_regression_of(&set.1).unwrap();
-            trends.entry(set.0.clone()).or_insert(vec![]).push((0.0 - days_back as f32,lr.0));
-        }
-    }
 
-    let trend_data = set_cases.iter().map(|sc| {
-        ( trends[sc.1].iter().map(|(_,v)| *v).collect::<Vec<f32>>(), sc.1.to_string() )
-    }).collect::<Vec<(Vec<f32>,String)>>();
+pub fn prevalence_factor_graph(factors: &Series) -> GraphSpec {
+    let factors = factors.map(|v| 1.0f32 / v);
 
-    let start_day = 0;
-    let max_days_back = trend_data[0].0.len()-7;
-    for days_back in (start_day..max_days_back).rev() {
-        let last_seven = trend_data.iter().map(|cases| (cases.1.to_string(), cases.0.iter().rev().skip(days_back).take(7).rev().enumerate().map(|(index, &v)| (index as f32,v) ).collect::<Vec<(f32,f32)>>()) ).collect::<Vec<(String, Vec<(f32,f32)>)>>();
+    GraphSpec::new("factors", "Factors", "factor")
+        .trace(Trace::new("", factors))
+}
 
-        for set in &last_seven {
-            let lr: (f32, f32) = linear_regression_of(&set.1).unwrap();
-            trends_of_trends.entry(set.0.clone()).or_insert(vec![]).push((0.0 - days_back as f32,lr.0));
-        }
+// the typical fraction of the final count that is known a number of days after Date_statistics
+fn completeness_graph(backfill: &Backfill) -> GraphSpec {
+    let delays = (0..=MAX_DELAY as i64).collect::<Vec<i64>>();
+    let mut y_data = vec![("All".to_string(), backfill.completeness(None))];
+    for group in AgeGroup::DECADES.iter() {
+        y_data.push((group.as_str().to_string(), backfill.completeness(Some(*group))));
     }
 
-    let labels = all_cases.iter().rev().skip(1).take(max_days_back-1).rev().map(|(name,_)| {
-        let mut dashed_name = name.clone();
-        dashed_name.insert(6,'-',);
-        dashed_name.insert(4,'-',);
-        return dashed_name;
-    }).collect::<Vec<String>>();
-
-    let begin = labels.iter().rev().skip(7).next().unwrap();
-    let end = labels.iter().last().unwrap();
-
-    let y_data = set_cases.iter().map(|sc| {
-        ( sc.1.to_string(), trends_of_trends[sc.1].iter().map(|(_,v)| *v).collect::<Vec<f32>>() )
-    }).collect::<Vec<(String, Vec<f32>)>>();
-
-    let layout = Layout::new().bar_mode(BarMode::Group)
-        .title(Title::new("Change of the change in active cases (7 day lin.reg.)").font(Font::new().color(NamedColor::Black).size(24).family("Droid Serif")))
-        .x_axis(Axis::new().type_(AxisType::Date).title(Title::new("Day").font(Font::new().color(NamedColor::Black).size(12).family("Droid Serif"))).range(vec![begin,end]))
-        .y_axis(Axis::new().title(Title::new("Increase/decrease").font(Font::new().color(NamedColor::Black).size(12).family("Droid Serif"))));
-
-    let mut plot = Plot::new();
-    y_data.iter().for_each(|(name, data)| {
-        plot.add_trace( Scatter::new( labels.clone(), data.clone() ).name(name) )
-    });
-    plot.set_layout(layout);
-
-    plot.to_html("graphs/trends_of_trends.html");
-    let html = plot.to_inline_html(Some(div_name));
-    overview_file.write_all(html.as_bytes());
-    overview_file.write_all(b"\n");
-
+    GraphSpec::new("completeness", "Completeness of the reported cases", "Fraction of final count")
+        .x_title("Days after Date_statistics")
+        .traces(y_data.into_iter().map(|(name, data)| Trace::numbers(&name, delays.clone(), data)))
 }
 
-pub fn get_scale_factors(all_cases: &BTreeMap<String, Vec<Case>>, all_prevalences: &BTreeMap<String, Prevalence>) -> Vec<f32> {
-    let case_counts = | cs: &BTreeMap<String, Vec<Case>> | -> Vec<f32> {
-        cs.iter().map(|(_, cases)| cases.len() as f32 ).collect::<Vec<f32>>()
-    };
-
-    let set_cases = active_cases(&case_counts(all_cases), 10);
-    let set_prevs = all_prevalences.iter().skip(10).map(|(_, prev)|  (prev.prev_up + prev.prev_low) as f32 / 2.0f32).collect::<Vec<f32>>();
-    let mut res: Vec<f32> = vec![];
-    for (c, p) in set_cases.iter().zip(set_prevs.iter()) {
-        res.push( *c / *p );
-    }
-    let last_value = *res.iter().last().unwrap();
-    while res.len() < set_cases.len() {
-        res.push(last_value);
+fn reporting_delay_graph(backfill: &Backfill) -> GraphSpec {
+    let delays = (0..=MAX_DELAY as i64).collect::<Vec<i64>>();
+    let mut y_data = vec![("All".to_string(), backfill.delay_distribution(None))];
+    for group in AgeGroup::DECADES.iter() {
+        y_data.push((group.as_str().to_string(), backfill.delay_distribution(Some(*group))));
     }
-    res
-}
-
-
-pub fn prevalence_factor_graph(all_cases: &BTreeMap<String, Vec<Case>>, all_prevalences: &BTreeMap<String, Prevalence>) {
-    let factors = get_scale_factors(all_cases, all_prevalences).iter().map(|&v| 1.0f32 / v).collect::<Vec<f32>>();
 
-    let labels = all_cases.iter().skip(10).map(|(name,_)| {
-        let mut dashed_name = name.clone();
-        dashed_name.insert(6,'-',);
-        dashed_name.insert(4,'-',);
-        return dashed_name;
-    }).collect::<Vec<String>>();
-
-    let begin = labels.iter().rev().skip(30).next().unwrap();
-    let end = labels.iter().last().unwrap();
+    for (name, distribution) in &y_data {
+        let mean = distribution.iter().enumerate().map(|(delay, fraction)| delay as f32 * fraction).sum::<f32>() / distribution.iter().sum::<f32>();
+        println!("mean reporting delay {}: {:.1} days", name, mean);
+    }
 
-    let layout = Layout::new().bar_mode(BarMode::Group)
-        .title(Title::new("Factors").font(Font::new().color(NamedColor::Black).size(24).family("Droid Serif")))
-        .x_axis(Axis::new().type_(AxisType::Date).title(Title::new("Day").font(Font::new().color(NamedColor::Black).size(12).family("Droid Serif"))).range(vec![begin,end]))
-        .y_axis(Axis::new().title(Title::new("factor").font(Font::new().color(NamedColor::Black).size(12).family("Droid Serif"))));
+    GraphSpec::new("reporting_delay", "Reporting delay per age group", "Fraction of cases")
+        .x_title("Days after Date_statistics")
+        .traces(y_data.into_iter().map(|(name, data)| Trace::numbers(&name, delays.clone(), data)))
+}
 
-    let mut plot = Plot::new();
-    plot.add_trace( Scatter::new( labels.clone(), factors.clone()));
-    plot.set_layout(layout);
-    plot.to_html("graphs/factors.html");
+// the count of each of the most recent Date_statistics in every later snapshot
+fn backfill_growth_graph(backfill: &Backfill) -> GraphSpec {
+    let latest = backfill.latest().unwrap();
+
+    let traces = (1..=MAX_DELAY as i64).rev().map(|days_back| {
+        let date = latest - Duration::days(days_back);
+        let growth = backfill.growth(date, None);
+        let delays = growth.iter().map(|(delay, _)| *delay).collect::<Vec<i64>>();
+        let counts = growth.iter().map(|(_, count)| *count as f32).collect::<Vec<f32>>();
+        Trace::numbers(&date.format("%Y-%m-%d").to_string(), delays, counts)
+    }).collect::<Vec<Trace>>();
+
+    GraphSpec::new("backfill", "Reported cases per Date_statistics by snapshot", "Reported cases")
+        .x_title("Days after Date_statistics")
+        .traces(traces)
 }
 
 
-pub fn calculate_peaks(all_cases: &BTreeMap<String, Vec<Case>>, all_prevalences: &BTreeMap<String, Prevalence>) {
-    let calculate_active_cases = | cs: &Vec<f32>, factors: &Vec<f32> | {
-        let ac = active_cases(&cs, 10);
-        let mut res: Vec<f32> = vec![];
-        for (f, v) in factors.iter().zip(ac.iter()) {
-            res.push(v/f);
+// the deaths per ISO week of death as reported by the RIVM against the deceased cases per week of
+// their Date_statistics, for the age groups that have deaths
+fn deaths_by_week_graph(all_cases: &CaseCube) -> GraphSpec {
+    let traces = AgeGroup::DECEASED.iter().flat_map(|&group| {
+        let in_group = move |key: &CubeKey| key.age_group == group;
+        let by_week_of_death = all_cases.deaths_by_week_of_death(&in_group);
+        let by_date_statistics = all_cases.series(&|key: &CubeKey| Filters::dead(key) && in_group(key));
+        if by_week_of_death.is_empty() {
+            return vec![];
         }
-        res
-    };
+        vec![
+            Trace::new(&format!("{} (week of death)", group.as_str()), by_week_of_death),
+            Trace::new(&format!("{} (Date_statistics)", group.as_str()), by_date_statistics).dotted(),
+        ]
+    }).collect::<Vec<Trace>>();
+
+    GraphSpec::new("deaths_by_week", "Deaths per week by age group", "Deaths per week")
+        .x_title("Week")
+        .days_shown(26)
+        .transform(Transform::Weekly)
+        .traces(traces)
+}
 
-    let case_counts = | cs: &BTreeMap<String, Vec<Case>> | -> Vec<f32> {
-        cs.iter().map(|(_, cases)| cases.len() as f32 ).collect::<Vec<f32>>()
+// the days the active cases of each age group peak, the most recent first
+pub fn find_peaks(all_cases: &CaseCube, all_prevalences: &TimeSeries<Prevalence>, active_window: usize) -> BTreeMap<&'static str, Vec<NaiveDate>> {
+    let factors = match get_scale_factors(&all_cases, &all_prevalences, active_window) {
+        Some(factors) => factors,
+        None => {
+            println!("Warning: no peaks, no day has both cases and a prevalence");
+            return BTreeMap::new();
+        }
     };
-
-    let factors = get_scale_factors(&all_cases, &all_prevalences).iter().skip(2).map(|&v| v).collect::<Vec<f32>>();
-
-    let set_cases: Vec<(Vec<f32>, &str)> = vec![
-        ( calculate_active_cases(&windowed_average(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_0_9    ])),2), &factors), " 0-9 "), 
-        ( calculate_active_cases(&windowed_average(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_10_19  ])),2), &factors), "10-19"),
-        ( calculate_active_cases(&windowed_average(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_20_29  ])),2), &factors), "20-29"),
-        ( calculate_active_cases(&windowed_average(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_30_39  ])),2), &factors), "30-39"),
-        ( calculate_active_cases(&windowed_average(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_40_49  ])),2), &factors), "40-49"),
-        ( calculate_active_cases(&windowed_average(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_50_59  ])),2), &factors), "50-59"),
-        ( calculate_active_cases(&windowed_average(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_60_69  ])),2), &factors), "60-69"),
-        ( calculate_active_cases(&windowed_average(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_70_79  ])),2), &factors), "70-79"),
-        ( calculate_active_cases(&windowed_average(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_80_89  ])),2), &factors), "80-89"),
-        ( calculate_active_cases(&windowed_average(&case_counts(&filter_cases(&all_cases, &vec![&Filters::age_group_90_plus])),2), &factors), "90-xx")
+    let calculate_active_cases = | cs: Series | cs.rolling_mean(2).active(active_window).divide(&factors);
+
+    let set_cases: Vec<(Series, &str)> = vec![
+        ( calculate_active_cases(all_cases.series(&Filters::age_group_0_9)    ), " 0-9 "),
+        ( calculate_active_cases(all_cases.series(&Filters::age_group_10_19)  ), "10-19"),
+        ( calculate_active_cases(all_cases.series(&Filters::age_group_20_29)  ), "20-29"),
+        ( calculate_active_cases(all_cases.series(&Filters::age_group_30_39)  ), "30-39"),
+        ( calculate_active_cases(all_cases.series(&Filters::age_group_40_49)  ), "40-49"),
+        ( calculate_active_cases(all_cases.series(&Filters::age_group_50_59)  ), "50-59"),
+        ( calculate_active_cases(all_cases.series(&Filters::age_group_60_69)  ), "60-69"),
+        ( calculate_active_cases(all_cases.series(&Filters::age_group_70_79)  ), "70-79"),
+        ( calculate_active_cases(all_cases.series(&Filters::age_group_80_89)  ), "80-89"),
+        ( calculate_active_cases(all_cases.series(&Filters::age_group_90_plus)), "90-xx")
     ];
 
-    assert!(set_cases[0].0.len()==factors.len());
-
-    let determine_peaks = | cs: &Vec<f32> | {
-        cs.iter().enumerate().skip(2).rev().skip(2).rev().filter(|(index, &v)|
-            cs[index-2] < cs[index-1] && 
-            cs[index-1] < v && 
-            v > cs[index+1] &&
-            cs[index+1] > cs[index+2]
-        ).map(|(index,_)| (index-1) as usize ).collect::<Vec<usize>>()
+    // the days with two rising days before and two falling days after, a missing day is no peak
+    let determine_peaks = | series: &Series | {
+        let day = |date: NaiveDate, offset: i64| series.get(date + Duration::days(offset)).unwrap_or(f32::NAN);
+        series.dates().iter().copied().filter(|&date|
+            day(date, -2) < day(date, -1) && 
+            day(date, -1) < day(date, 0) && 
+            day(date, 0) > day(date, 1) &&
+            day(date, 1) > day(date, 2)
+        ).collect::<Vec<NaiveDate>>()
     };
 
-    let labels = all_cases.iter().skip(10).map(|(name,_)| {
-        let mut dashed_name = name.clone();
-        dashed_name.insert(6,'-',);
-        dashed_name.insert(4,'-',);
-        let parts = dashed_name.split('-').collect::<Vec<&str>>();
-        return NaiveDate::from_ymd(parts[0].parse::<i32>().unwrap(), parts[1].parse::<u32>().unwrap(), parts[2].parse::<u32>().unwrap());
-    }).collect::<Vec<NaiveDate>>();
+    set_cases.iter().fold(BTreeMap::new(), |mut acc, (series, name)| {
+        acc.entry(*name).or_insert(determine_peaks(series).into_iter().rev().collect::<Vec<NaiveDate>>()); acc
+    })
+}
 
-    let mut peaks = set_cases.iter().fold(BTreeMap::new(), |mut acc, (v, name)| {
-        acc.entry(*name).or_insert(determine_peaks(v).iter().rev().map(|&index| labels[index].clone()).collect::<Vec<NaiveDate>>()); acc
-    });
+pub fn print_peaks(peaks: &BTreeMap<&'static str, Vec<NaiveDate>>) {
+    for (name, dates) in peaks {
+        let dates = dates.iter().rev().map(|date| date.format("%Y-%m-%d").to_string()).collect::<Vec<String>>();
+        println!("{} => {}", name, dates.join(", "));
+    }
+}
 
+// groups the peaks of the age groups that follow each other within 8 days, and writes a graph of
+// each cluster to clusters_dir
+pub fn calculate_clusters(mut peaks: BTreeMap<&'static str, Vec<NaiveDate>>, clusters_dir: &Path) -> Result<(), CoronaDataError> {
     let mut clusters: Vec<BTreeMap<NaiveDate, Vec<String>>> = vec![];
 
     while peaks.iter().fold(0, |acc, (_, dates)| acc + dates.len() ) > 0 {
@@ -558,7 +944,7 @@ pub fn calculate_peaks(all_cases: &BTreeMap<String, Vec<Case>>, all_prevalences:
             let first_date = cluster.iter().fold( current_date, |acc , (&d, _)| std::cmp::min(acc, d) );
             let last_date = cluster.iter().fold( current_date, |acc , (&d, _)| std::cmp::max(acc, d) );
             
-            create_cluster_graph(&cluster);
+            create_cluster_graph(&cluster, clusters_dir)?;
 
             clusters.push(cluster.clone());
             println!("cluster of {} days:", (last_date - first_date).num_days());
@@ -571,6 +957,7 @@ pub fn calculate_peaks(all_cases: &BTreeMap<String, Vec<Case>>, all_prevalences:
             println!("");
         }
     }
+    Ok(())
 }
 
 
@@ -588,7 +975,7 @@ struct Graph {
     edge_names: Vec<String>
 }
 
-pub fn create_cluster_graph(cluster: &BTreeMap<NaiveDate, Vec<String>>) {
+pub fn create_cluster_graph(cluster: &BTreeMap<NaiveDate, Vec<String>>, clusters_dir: &Path) -> Result<(), CoronaDataError> {
     let nodes = vec![
         " 0-9 ",
         "10-19",
@@ -633,12 +1020,14 @@ pub fn create_cluster_graph(cluster: &BTreeMap<NaiveDate, Vec<String>>) {
         let graph_name: String = format!("cluster{}{}{}",d.year(),d.month(),d.day());
         let graph = Graph { name: graph_name, selected_nodes: selected, selected_edges: targets, nodes: nodes.clone(), edges: edges.clone(), edge_names: edge_names.clone() };
     
-        let mut output = File::create(format!("clusters/cluster_{:?}_{:?}_{:05}.dot", first_date, last_date, selection_count).as_str()).unwrap();
-        dot::render(&graph, &mut output).unwrap();
+        let path = clusters_dir.join(format!("cluster_{:?}_{:?}_{:05}.dot", first_date, last_date, selection_count));
+        let mut output = File::create(&path).map_err(|e| CoronaDataError::io(&path, e))?;
+        dot::render(&graph, &mut output).map_err(|e| CoronaDataError::io(&path, e))?;
         selection_count += 1;
+        Ok(())
     };
 
-    from_dates.iter().for_each(|selection_date| {
+    for selection_date in from_dates.iter() {
         let mut selected_nodes: Vec<&'static str> = vec![];
         let mut selected_edges: Vec<String> = vec![];
         for (f, t) in from_dates.iter().zip(to_dates.iter()) {
@@ -655,9 +1044,8 @@ pub fn create_cluster_graph(cluster: &BTreeMap<NaiveDate, Vec<String>>) {
             }
         }
 
-        save_dot(from_dates[0], selected_nodes, selected_edges);
-    
-    });
+        save_dot(from_dates[0], selected_nodes, selected_edges)?;
+    }
 
 
     let selection_date = last_date;
@@ -670,10 +1058,11 @@ pub fn create_cluster_graph(cluster: &BTreeMap<NaiveDate, Vec<String>>) {
         }
     }
 
-    save_dot(from_dates[0], selected_nodes, vec![]);
+    save_dot(from_dates[0], selected_nodes, vec![])?;
 
     let clip_name = format!("cluster_{:?}_{:?}", first_date, last_date);
-    let mut clip_creation_script = File::create(format!("clusters/create_{}.sh", clip_name)).unwrap();
+    let script_path = clusters_dir.join(format!("create_{}.sh", clip_name));
+    let mut clip_creation_script = File::create(&script_path).map_err(|e| CoronaDataError::io(&script_path, e))?;
     // clip_creation_script.write_all(b"ffmpeg -framerate 1 -i ");
     // clip_creation_script.write_all(clip_name.as_bytes());
     // clip_creation_script.write_all(b"_%05d.png -s:v 1280x720 -c:v libx264 -profile:v high -crf 20 -pix_fmt yuv420p ");
@@ -686,12 +1075,9 @@ pub fn create_cluster_graph(cluster: &BTreeMap<NaiveDate, Vec<String>>) {
     // clip_creation_script.write_all(b".gif");
 
 
-    clip_creation_script.write_all(b"ffmpeg -framerate 1 -i ");
-    clip_creation_script.write_all(clip_name.as_bytes());
-    clip_creation_script.write_all(b"_%05d.png -vf \"fps=1,scale=1280:-1:flags=lanczos,split[s0][s1];[s0]palettegen[p];[s1][p]paletteuse\" -loop 0 ");
-    clip_creation_script.write_all(clip_name.as_bytes());
-    clip_creation_script.write_all(b".gif");
-
+    write!(clip_creation_script,
+        "ffmpeg -framerate 1 -i {}_%05d.png -vf \"fps=1,scale=1280:-1:flags=lanczos,split[s0][s1];[s0]palettegen[p];[s1][p]paletteuse\" -loop 0 {}.gif",
+        clip_name, clip_name).map_err(|e| CoronaDataError::io(&script_path, e))
 }
 
 impl<'a> dot::Labeller<'a, Nd, Ed<'a>> for Graph {
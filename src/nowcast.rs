@@ -0,0 +1,65 @@
+use crate::backfill::{Backfill, MAX_DELAY};
+use crate::cases::AgeGroup;
+use std::collections::BTreeMap;
+
+// Nowcasting: the counts of the most recent days are too low because cases are still being
+// reported. Dividing them by the completeness at their delay (from the backfill analysis of the
+// archived snapshots) estimates the final count. The spread of the completeness of the single
+// days gives the uncertainty band.
+
+// below this the estimate would mostly amplify noise, such days are left as they are
+const MIN_COMPLETENESS: f32 = 0.2;
+const MIN_SAMPLES: usize = 5;
+// the quantiles of the completeness used for the band
+const LOW_QUANTILE: f32 = 0.1;
+const HIGH_QUANTILE: f32 = 0.9;
+
+// the factors to multiply a count with: the estimate and the band around it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Band {
+    pub low: f32,
+    pub mid: f32,
+    pub high: f32,
+}
+
+pub struct Nowcast {
+    // per age group (None for all cases together) the factors per delay, None where there is no estimate
+    factors: BTreeMap<Option<AgeGroup>, Vec<Option<Band>>>,
+}
+
+impl Nowcast {
+    pub fn from_backfill(backfill: &Backfill) -> Nowcast {
+        let mut factors = BTreeMap::new();
+        for group in std::iter::once(None).chain(AgeGroup::DECADES.iter().map(|&group| Some(group))) {
+            let completeness = backfill.completeness(group);
+            let samples = backfill.completeness_samples(group);
+            let bands = completeness.iter().zip(samples).map(|(&mid, mut samples)| {
+                if samples.len() < MIN_SAMPLES || mid.is_nan() || mid < MIN_COMPLETENESS {
+                    return None;
+                }
+                samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                // the lowest completeness gives the highest estimate
+                let low = quantile(&samples, HIGH_QUANTILE).max(mid);
+                let high = quantile(&samples, LOW_QUANTILE).clamp(MIN_COMPLETENESS, mid);
+                Some(Band { low: 1.0 / low, mid: 1.0 / mid, high: 1.0 / high })
+            }).collect();
+            factors.insert(group, bands);
+        }
+        Nowcast { factors }
+    }
+
+    // the factors for a count reported `delay` days after its Date_statistics, the curve of all
+    // cases is used for age groups without an estimate; None means no correction
+    pub fn factor(&self, group: AgeGroup, delay: i64) -> Option<Band> {
+        if delay < 0 || delay as usize > MAX_DELAY {
+            return None;
+        }
+        let band = |group: Option<AgeGroup>| self.factors.get(&group).and_then(|bands| bands[delay as usize]);
+        band(Some(group)).or_else(|| band(None))
+    }
+}
+
+// of sorted values
+fn quantile(sorted: &[f32], q: f32) -> f32 {
+    sorted[((sorted.len() - 1) as f32 * q).round() as usize]
+}
@@ -0,0 +1,398 @@
+use crate::cases::*;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufReader;
+
+// A small filter language for selecting cases, e.g.
+//
+//    age in [60-69,70-79] and sex = Female and province = "Noord-Holland" and not deceased
+//
+// Fields: age, sex, province, ggd, hospital, deceased, type (DOO/DPL/DON).
+// The boolean fields hospital and deceased can be used on their own.
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Condition(Condition),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Age(Vec<AgeGroup>),
+    Sex(Vec<Sex>),
+    Province(Vec<String>),
+    Ggd(Vec<String>),
+    Hospital(Vec<YesNoUnknown>),
+    Deceased(Vec<YesNoUnknown>),
+    Type(Vec<DateStatisticsType>),
+}
+
+impl Expr {
+    pub fn parse(input: &str) -> Result<Expr, String> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let expr = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some(token) => Err(format!("unexpected {} after end of expression", token.describe())),
+        }
+    }
+
+    pub fn matches<C: CaseFields>(&self, c: &C) -> bool {
+        match self {
+            Expr::And(l, r)         => l.matches(c) && r.matches(c),
+            Expr::Or(l, r)          => l.matches(c) || r.matches(c),
+            Expr::Not(e)            => !e.matches(c),
+            Expr::Condition(cond)   => cond.matches(c),
+        }
+    }
+}
+
+impl Condition {
+    pub fn matches<C: CaseFields>(&self, c: &C) -> bool {
+        match self {
+            Condition::Age(groups)      => groups.contains(&c.age_group()),
+            Condition::Sex(values)      => values.contains(&c.sex()),
+            Condition::Province(names)  => names.iter().any(|name| name.eq_ignore_ascii_case(c.province())),
+            Condition::Ggd(names)       => names.iter().any(|name| name.eq_ignore_ascii_case(c.municipal_health_service())),
+            Condition::Hospital(values) => values.contains(&c.hospital_admission()),
+            Condition::Deceased(values) => values.contains(&c.deceased()),
+            Condition::Type(types)      => types.contains(&c.date_statistics_type()),
+        }
+    }
+}
+
+pub struct Cohort {
+    pub name: String,
+    pub expr: Expr,
+}
+
+impl Cohort {
+    pub fn parse(input: &str) -> Result<Cohort, String> {
+        Ok(Cohort { name: input.trim().to_string(), expr: Expr::parse(input)? })
+    }
+}
+
+// one expression per line, empty lines and lines starting with # are ignored
+pub fn read_cohort_file(filename: &str) -> Result<Vec<Cohort>, String> {
+    let file = File::open(filename).map_err(|e| format!("{}: {}", filename, e))?;
+    let mut res: Vec<Cohort> = vec![];
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("{}: {}", filename, e))?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        res.push(Cohort::parse(line).map_err(|e| format!("{} line {}: {}", filename, index + 1, e))?);
+    }
+    Ok(res)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Equals,
+    NotEquals,
+    Comma,
+    OpenParen,
+    CloseParen,
+    OpenBracket,
+    CloseBracket,
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(w)      => format!("'{}'", w),
+            Token::Quoted(w)    => format!("\"{}\"", w),
+            Token::Equals       => "'='".to_string(),
+            Token::NotEquals    => "'!='".to_string(),
+            Token::Comma        => "','".to_string(),
+            Token::OpenParen    => "'('".to_string(),
+            Token::CloseParen   => "')'".to_string(),
+            Token::OpenBracket  => "'['".to_string(),
+            Token::CloseBracket => "']'".to_string(),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self, Token::Word(w) if w.eq_ignore_ascii_case(keyword))
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens: Vec<Token> = vec![];
+    let mut chars = input.chars().peekable();
+
+    while let Some(&ch) = chars.peek() {
+        match ch {
+            ' ' | '\t' | '\n' | '\r' => { chars.next(); },
+            '(' => { chars.next(); tokens.push(Token::OpenParen); },
+            ')' => { chars.next(); tokens.push(Token::CloseParen); },
+            '[' => { chars.next(); tokens.push(Token::OpenBracket); },
+            ']' => { chars.next(); tokens.push(Token::CloseBracket); },
+            ',' => { chars.next(); tokens.push(Token::Comma); },
+            '=' => { chars.next(); tokens.push(Token::Equals); },
+            '!' => {
+                chars.next();
+                if chars.next() != Some('=') {
+                    return Err("expected '=' after '!'".to_string());
+                }
+                tokens.push(Token::NotEquals);
+            },
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err(format!("unterminated string \"{}", value)),
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+            },
+            _ => {
+                let mut value = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "()[],=!\"".contains(c) {
+                        break;
+                    }
+                    value.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(value));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some(ref token) if *token == expected => Ok(()),
+            Some(token) => Err(format!("expected {}, found {}", expected.describe(), token.describe())),
+            None => Err(format!("expected {}, found end of expression", expected.describe())),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_and()?;
+        while self.peek().is_some_and(|t| t.is_keyword("or")) {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_unary()?;
+        while self.peek().is_some_and(|t| t.is_keyword("and")) {
+            self.next();
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if self.peek().is_some_and(|t| t.is_keyword("not")) {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::OpenParen) => {
+                let expr = self.parse_or()?;
+                self.expect(Token::CloseParen)?;
+                Ok(expr)
+            },
+            Some(Token::Word(field)) => self.parse_condition(&field.to_lowercase()),
+            Some(token) => Err(format!("expected a field name, found {}", token.describe())),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    fn parse_condition(&mut self, field: &str) -> Result<Expr, String> {
+        let negate;
+        let values = match self.peek() {
+            Some(Token::Equals) => { self.next(); negate = false; vec![self.parse_value()?] },
+            Some(Token::NotEquals) => { self.next(); negate = true; vec![self.parse_value()?] },
+            Some(t) if t.is_keyword("in") => { self.next(); negate = false; self.parse_list()? },
+            _ => {
+                // a bare boolean field means "= Yes"
+                return match field {
+                    "hospital" | "deceased" => build_condition(field, &["Yes".to_string()]).map(Expr::Condition),
+                    _ => Err(format!("field '{}' needs a comparison", field)),
+                }
+            }
+        };
+
+        let condition = Expr::Condition(build_condition(field, &values)?);
+        Ok(if negate { Expr::Not(Box::new(condition)) } else { condition })
+    }
+
+    fn parse_value(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Word(value)) | Some(Token::Quoted(value)) => Ok(value),
+            Some(token) => Err(format!("expected a value, found {}", token.describe())),
+            None => Err("expected a value, found end of expression".to_string()),
+        }
+    }
+
+    fn parse_list(&mut self) -> Result<Vec<String>, String> {
+        self.expect(Token::OpenBracket)?;
+        let mut values = vec![self.parse_value()?];
+        while self.peek() == Some(&Token::Comma) {
+            self.next();
+            values.push(self.parse_value()?);
+        }
+        self.expect(Token::CloseBracket)?;
+        Ok(values)
+    }
+}
+
+fn build_condition(field: &str, values: &[String]) -> Result<Condition, String> {
+    fn parse_all<T: std::str::FromStr<Err = String>>(values: &[String]) -> Result<Vec<T>, String> {
+        values.iter().map(|v| v.parse::<T>()).collect()
+    }
+
+    match field {
+        "age"      => Ok(Condition::Age(parse_all(values)?)),
+        "sex"      => Ok(Condition::Sex(parse_all(values)?)),
+        "province" => Ok(Condition::Province(values.to_vec())),
+        "ggd"      => Ok(Condition::Ggd(values.to_vec())),
+        "hospital" => Ok(Condition::Hospital(parse_all(values)?)),
+        "deceased" => Ok(Condition::Deceased(parse_all(values)?)),
+        "type"     => Ok(Condition::Type(parse_all(values)?)),
+        _ => Err(format!("unknown field '{}', expected one of age, sex, province, ggd, hospital, deceased, type", field)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestCase {
+        age_group: AgeGroup,
+        sex: Sex,
+        province: &'static str,
+        deceased: YesNoUnknown,
+    }
+
+    impl CaseFields for TestCase {
+        fn age_group(&self) -> AgeGroup { self.age_group }
+        fn sex(&self) -> Sex { self.sex }
+        fn province(&self) -> &str { self.province }
+        fn municipal_health_service(&self) -> &str { "GGD Kennemerland" }
+        fn hospital_admission(&self) -> YesNoUnknown { YesNoUnknown::No }
+        fn deceased(&self) -> YesNoUnknown { self.deceased }
+        fn date_statistics_type(&self) -> DateStatisticsType { DateStatisticsType::DiseaseOnset }
+    }
+
+    fn case(age_group: AgeGroup, sex: Sex, deceased: YesNoUnknown) -> TestCase {
+        TestCase { age_group, sex, province: "Noord-Holland", deceased }
+    }
+
+    fn condition(condition: Condition) -> Box<Expr> {
+        Box::new(Expr::Condition(condition))
+    }
+
+    #[test]
+    fn tokenize_splits_on_punctuation() {
+        assert_eq!(tokenize("age in [60-69,70-79] and province != \"Noord Holland\"").unwrap(), vec![
+            Token::Word("age".to_string()), Token::Word("in".to_string()), Token::OpenBracket,
+            Token::Word("60-69".to_string()), Token::Comma, Token::Word("70-79".to_string()), Token::CloseBracket,
+            Token::Word("and".to_string()), Token::Word("province".to_string()), Token::NotEquals,
+            Token::Quoted("Noord Holland".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn tokenize_rejects_unterminated_string() {
+        assert_eq!(tokenize("province = \"Utrecht").unwrap_err(), "unterminated string \"Utrecht");
+        assert!(Expr::parse("province = \"Utrecht").is_err());
+    }
+
+    #[test]
+    fn tokenize_rejects_lone_exclamation_mark() {
+        assert!(tokenize("sex ! Male").is_err());
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_which_binds_tighter_than_or() {
+        let expr = Expr::parse("not deceased and sex = Male or age = 90+").unwrap();
+        assert_eq!(expr, Expr::Or(
+            Box::new(Expr::And(
+                Box::new(Expr::Not(condition(Condition::Deceased(vec![YesNoUnknown::Yes])))),
+                condition(Condition::Sex(vec![Sex::Male])),
+            )),
+            condition(Condition::Age(vec![AgeGroup::Age90Plus])),
+        ));
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        let expr = Expr::parse("sex = Male and (age = 90+ or deceased)").unwrap();
+        assert_eq!(expr, Expr::And(
+            condition(Condition::Sex(vec![Sex::Male])),
+            Box::new(Expr::Or(
+                condition(Condition::Age(vec![AgeGroup::Age90Plus])),
+                condition(Condition::Deceased(vec![YesNoUnknown::Yes])),
+            )),
+        ));
+    }
+
+    #[test]
+    fn not_equals_is_a_negated_condition() {
+        assert_eq!(Expr::parse("sex != Female").unwrap(), Expr::Not(condition(Condition::Sex(vec![Sex::Female]))));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(Expr::parse("").is_err());
+        assert!(Expr::parse("age").is_err());
+        assert!(Expr::parse("colour = red").is_err());
+        assert!(Expr::parse("age = 100-109").is_err());
+        assert!(Expr::parse("(sex = Male").is_err());
+        assert!(Expr::parse("age in [60-69").is_err());
+        assert!(Expr::parse("sex = Male sex = Female").is_err());
+    }
+
+    #[test]
+    fn matches_evaluates_the_expression() {
+        let expr = Expr::parse("not deceased and sex = Male or age in [80-89, 90+]").unwrap();
+        assert!(expr.matches(&case(AgeGroup::Age20_29, Sex::Male, YesNoUnknown::No)));
+        assert!(!expr.matches(&case(AgeGroup::Age20_29, Sex::Male, YesNoUnknown::Yes)));
+        assert!(!expr.matches(&case(AgeGroup::Age20_29, Sex::Female, YesNoUnknown::No)));
+        assert!(expr.matches(&case(AgeGroup::Age90Plus, Sex::Female, YesNoUnknown::Yes)));
+    }
+
+    #[test]
+    fn names_are_compared_ignoring_case() {
+        let expr = Expr::parse("province = \"noord-holland\"").unwrap();
+        assert!(expr.matches(&case(AgeGroup::Age20_29, Sex::Male, YesNoUnknown::No)));
+    }
+}
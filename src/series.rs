@@ -0,0 +1,216 @@
+use crate::cases::growth_factor;
+use crate::timeseries::{days, iso_week, TimeSeries};
+use chrono::NaiveDate;
+
+// A daily series: the values with the day each one belongs to. The transforms keep the days of the
+// values they produce, so a chain like
+//
+//    cases.active(10).ratio().rolling_mean(5)
+//
+// is dated from the first day that has a value, without counting the days every step drops.
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Series {
+    dates: Vec<NaiveDate>,
+    values: Vec<f32>,
+}
+
+impl Series {
+    pub fn new(dates: Vec<NaiveDate>, values: Vec<f32>) -> Series {
+        assert_eq!(dates.len(), values.len(), "a series needs a date for every value");
+        Series { dates, values }
+    }
+
+    pub fn dates(&self) -> &[NaiveDate] {
+        &self.dates
+    }
+
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn last(&self) -> Option<(NaiveDate, f32)> {
+        Some((*self.dates.last()?, *self.values.last()?))
+    }
+
+    pub fn get(&self, date: NaiveDate) -> Option<f32> {
+        self.dates.binary_search(&date).ok().map(|index| self.values[index])
+    }
+
+    // "2020-12-26"
+    pub fn labels(&self) -> Vec<String> {
+        self.dates.iter().map(|date| date.format("%Y-%m-%d").to_string()).collect()
+    }
+
+    // every day from the first to the last, a day without a value is NaN, e.g. a day the hospitalizations
+    // of only one of IC and ward are known
+    fn daily(&self) -> Series {
+        match (self.dates.first(), self.dates.last()) {
+            (Some(&first), Some(&last)) if (last - first).num_days() as usize + 1 != self.len() => {
+                let dates = days(first, last).collect::<Vec<NaiveDate>>();
+                let values = dates.iter().map(|&date| self.get(date).unwrap_or(f32::NAN)).collect();
+                Series { dates, values }
+            }
+            _ => self.clone(),
+        }
+    }
+
+    // the values computed from the daily values by position, dated by the last days
+    fn by_position(&self, f: impl Fn(&Vec<f32>) -> Vec<f32>) -> Series {
+        let daily = self.daily();
+        let values = f(&daily.values);
+        let dates = daily.dates[daily.dates.len() - values.len()..].to_vec();
+        Series { dates, values }
+    }
+
+    // the average of the last `window` days, from the first full window on
+    pub fn rolling_mean(&self, window: usize) -> Series {
+        self.rolling_sum(window).map(|sum| sum / window as f32)
+    }
+
+    // the sum of the last `window` days, from the first full window on
+    pub fn rolling_sum(&self, window: usize) -> Series {
+        self.by_position(|values| values.windows(window).map(|days| days.iter().sum()).collect())
+    }
+
+    // the cases that are still infectious: the sum of the day and the `window` days before it
+    pub fn active(&self, window: usize) -> Series {
+        self.rolling_sum(window + 1)
+    }
+
+    // each value divided by the one of the day before
+    pub fn ratio(&self) -> Series {
+        self.by_position(growth_factor)
+    }
+
+    // the sum per ISO week, dated by its Monday, the first and the last week can be partial
+    pub fn weekly(&self) -> Series {
+        TimeSeries::from_series(self).resample(iso_week, |sum: &mut f32, &v| *sum += v).series(|&v| v)
+    }
+
+    // divided by the value of the same day in other, only the days both have
+    pub fn divide(&self, other: &Series) -> Series {
+        self.zip_with(other, |a, b| a / b)
+    }
+
+    pub fn map(&self, f: impl Fn(f32) -> f32) -> Series {
+        Series { dates: self.dates.clone(), values: self.values.iter().map(|&v| f(v)).collect() }
+    }
+
+    // the running total
+    pub fn cumulative(&self) -> Series {
+        let values = self.values.iter().scan(0.0, |sum, v| { *sum += v; Some(*sum) }).collect();
+        Series { dates: self.dates.clone(), values }
+    }
+
+    // only the days of the range, inclusive
+    pub fn between(&self, from: NaiveDate, until: NaiveDate) -> Series {
+        let (dates, values) = self.dates.iter().zip(&self.values).filter(|(date, _)| from <= **date && **date <= until).unzip();
+        Series { dates, values }
+    }
+
+    // f of the values of the days both series have
+    pub fn zip_with(&self, other: &Series, f: impl Fn(f32, f32) -> f32) -> Series {
+        let (dates, values) = self.dates.iter().zip(&self.values)
+            .filter_map(|(&date, &a)| other.get(date).map(|b| (date, f(a, b))))
+            .unzip();
+        Series { dates, values }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2020, 12, day).unwrap()
+    }
+
+    // the values from the first of December on
+    fn series(values: &[f32]) -> Series {
+        Series::new(days(date(1), date(values.len() as u32)).collect(), values.to_vec())
+    }
+
+    fn dated(first: u32, values: &[f32]) -> Series {
+        Series::new(days(date(first), date(first + values.len() as u32 - 1)).collect(), values.to_vec())
+    }
+
+    #[test]
+    fn transforms_keep_the_days_of_their_values() {
+        let cases = series(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(cases.rolling_mean(2), dated(2, &[1.5, 2.5, 3.5, 4.5, 5.5]));
+        assert_eq!(cases.rolling_sum(2), dated(2, &[3.0, 5.0, 7.0, 9.0, 11.0]));
+        assert_eq!(cases.active(2), dated(3, &[6.0, 9.0, 12.0, 15.0]));
+        assert_eq!(cases.ratio(), dated(2, &[2.0, 1.5, 4.0 / 3.0, 1.25, 1.2]));
+        assert_eq!(cases.active(2).ratio(), dated(4, &[1.5, 12.0 / 9.0, 1.25]));
+    }
+
+    #[test]
+    fn transforms_of_a_short_series_are_empty() {
+        let cases = series(&[1.0, 2.0]);
+        assert!(cases.rolling_mean(3).is_empty());
+        assert!(cases.rolling_sum(5).is_empty());
+        assert!(cases.active(2).is_empty());
+        assert!(Series::default().ratio().is_empty());
+    }
+
+    #[test]
+    fn transforms_do_not_take_the_days_around_a_gap_for_adjacent_days() {
+        let cases = Series::new(vec![date(1), date(2), date(4), date(5)], vec![1.0, 2.0, 4.0, 5.0]);
+        let average = cases.rolling_mean(2);
+        assert_eq!(average.dates(), &[date(2), date(3), date(4), date(5)]);
+        assert_eq!(average.get(date(2)), Some(1.5));
+        assert!(average.get(date(3)).unwrap().is_nan());
+        assert!(average.get(date(4)).unwrap().is_nan());
+        assert_eq!(average.get(date(5)), Some(4.5));
+        assert_eq!(cases.ratio().get(date(5)), Some(1.25));
+        assert!(cases.ratio().get(date(4)).unwrap().is_nan());
+    }
+
+    #[test]
+    fn weekly_sums_per_iso_week() {
+        // the first of December 2020 is a Tuesday
+        let cases = series(&[1.0; 10]);
+        assert_eq!(cases.weekly(), Series::new(vec![NaiveDate::from_ymd_opt(2020, 11, 30).unwrap(), date(7)], vec![6.0, 4.0]));
+    }
+
+    #[test]
+    fn divide_and_zip_with_only_keep_the_days_both_have() {
+        let a = series(&[2.0, 4.0, 6.0, 8.0]);
+        let b = dated(3, &[2.0, 4.0, 5.0]);
+        assert_eq!(a.divide(&b), dated(3, &[3.0, 2.0]));
+        assert_eq!(a.zip_with(&b, |x, y| x - y), dated(3, &[4.0, 4.0]));
+    }
+
+    #[test]
+    fn map_cumulative_and_between() {
+        let cases = series(&[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(cases.map(|v| v * 10.0), series(&[10.0, 20.0, 30.0, 40.0]));
+        assert_eq!(cases.cumulative(), series(&[1.0, 3.0, 6.0, 10.0]));
+        assert_eq!(cases.between(date(2), date(3)), dated(2, &[2.0, 3.0]));
+        assert!(cases.between(date(5), date(9)).is_empty());
+    }
+
+    #[test]
+    fn get_and_last() {
+        let cases = dated(3, &[1.0, 2.0]);
+        assert_eq!(cases.get(date(4)), Some(2.0));
+        assert_eq!(cases.get(date(1)), None);
+        assert_eq!(cases.last(), Some((date(4), 2.0)));
+        assert_eq!(Series::default().last(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn new_needs_a_date_for_every_value() {
+        Series::new(vec![date(1)], vec![1.0, 2.0]);
+    }
+}
@@ -0,0 +1,230 @@
+use crate::series::Series;
+use chrono::{Datelike, Duration, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::btree_map;
+use std::collections::{BTreeMap, BTreeSet};
+use std::iter::FromIterator;
+
+// Values of any kind by day, e.g. the hospitalizations or the prevalences as read from their
+// files. Unlike Series it does not assume a value for every day: days can be missing, which
+// gaps reports and reindex fills, and two sources are combined by date with union or intersection.
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TimeSeries<T> {
+    values: BTreeMap<NaiveDate, T>,
+}
+
+impl<T> Default for TimeSeries<T> {
+    fn default() -> Self {
+        TimeSeries { values: BTreeMap::new() }
+    }
+}
+
+impl<T> TimeSeries<T> {
+    pub fn insert(&mut self, date: NaiveDate, value: T) {
+        self.values.insert(date, value);
+    }
+
+    pub fn get(&self, date: NaiveDate) -> Option<&T> {
+        self.values.get(&date)
+    }
+
+    pub fn entry(&mut self, date: NaiveDate) -> btree_map::Entry<'_, NaiveDate, T> {
+        self.values.entry(date)
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.values.contains_key(&date)
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn first_date(&self) -> Option<NaiveDate> {
+        self.values.keys().next().copied()
+    }
+
+    pub fn last_date(&self) -> Option<NaiveDate> {
+        self.values.keys().next_back().copied()
+    }
+
+    pub fn dates(&self) -> impl DoubleEndedIterator<Item = NaiveDate> + '_ {
+        self.values.keys().copied()
+    }
+
+    pub fn values(&self) -> impl DoubleEndedIterator<Item = &T> {
+        self.values.values()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (NaiveDate, &T)> {
+        self.values.iter().map(|(date, value)| (*date, value))
+    }
+
+    // without the days after until, the removed days are returned
+    pub fn split_after(&mut self, until: NaiveDate) -> TimeSeries<T> {
+        TimeSeries { values: self.values.split_off(&(until + Duration::days(1))) }
+    }
+
+    // the days between the first and the last that have no value
+    pub fn gaps(&self) -> Vec<NaiveDate> {
+        match (self.first_date(), self.last_date()) {
+            (Some(first), Some(last)) => days(first, last).filter(|date| !self.contains(*date)).collect(),
+            _ => vec![],
+        }
+    }
+
+    pub fn map<U>(&self, f: impl Fn(&T) -> U) -> TimeSeries<U> {
+        self.iter().map(|(date, value)| (date, f(value))).collect()
+    }
+
+    // the values as numbers, for the graphs
+    pub fn series(&self, f: impl Fn(&T) -> f32) -> Series {
+        Series::new(self.dates().collect(), self.values().map(f).collect())
+    }
+}
+
+impl<T: Clone> TimeSeries<T> {
+    // exactly the given days, the ones without a value are filled
+    pub fn reindex(&self, dates: impl IntoIterator<Item = NaiveDate>, fill: impl Fn(NaiveDate) -> T) -> TimeSeries<T> {
+        dates.into_iter().map(|date| (date, self.get(date).cloned().unwrap_or_else(|| fill(date)))).collect()
+    }
+
+    // the days of either, with the values each of them has
+    pub fn union<U: Clone>(&self, other: &TimeSeries<U>) -> TimeSeries<(Option<T>, Option<U>)> {
+        let dates = self.dates().chain(other.dates()).collect::<BTreeSet<NaiveDate>>();
+        dates.into_iter().map(|date| (date, (self.get(date).cloned(), other.get(date).cloned()))).collect()
+    }
+
+    // only the days both have
+    pub fn intersection<U: Clone>(&self, other: &TimeSeries<U>) -> TimeSeries<(T, U)> {
+        self.iter().filter_map(|(date, value)| other.get(date).map(|other_value| (date, (value.clone(), other_value.clone())))).collect()
+    }
+
+    // the values combined per period, the period of a day is given by its first day, e.g. the
+    // Monday of its ISO week
+    pub fn resample<R: Default>(&self, period: impl Fn(NaiveDate) -> NaiveDate, add: impl Fn(&mut R, &T)) -> TimeSeries<R> {
+        let mut res: TimeSeries<R> = TimeSeries::default();
+        for (date, value) in self.iter() {
+            add(res.entry(period(date)).or_default(), value);
+        }
+        res
+    }
+}
+
+impl TimeSeries<f32> {
+    pub fn from_series(series: &Series) -> TimeSeries<f32> {
+        series.dates().iter().copied().zip(series.values().iter().copied()).collect()
+    }
+}
+
+impl<T> FromIterator<(NaiveDate, T)> for TimeSeries<T> {
+    fn from_iter<I: IntoIterator<Item = (NaiveDate, T)>>(iter: I) -> Self {
+        TimeSeries { values: iter.into_iter().collect() }
+    }
+}
+
+impl<T> IntoIterator for TimeSeries<T> {
+    type Item = (NaiveDate, T);
+    type IntoIter = btree_map::IntoIter<NaiveDate, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.values.into_iter()
+    }
+}
+
+// The days of the analysis window: from the day after from up to and including the last day of
+// the cases. Every count series gets an entry for each of its days, a day without anything
+// reported is an explicit zero, so a position in a series is always a day.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Calendar {
+    pub first: NaiveDate,
+    pub last: NaiveDate,
+}
+
+impl Calendar {
+    pub fn new(from: NaiveDate, last: NaiveDate) -> Calendar {
+        Calendar { first: from + Duration::days(1), last }
+    }
+
+    pub fn days(&self) -> impl Iterator<Item = NaiveDate> {
+        days(self.first, self.last)
+    }
+
+    // every day from the first day of the calendar up to the last day of the series, the days after
+    // it are not reported yet, which is not the same as zero
+    pub fn fill<T: Clone>(&self, series: &TimeSeries<T>, zero: T) -> TimeSeries<T> {
+        match series.last_date() {
+            Some(last) => series.reindex(days(self.first, last), |_| zero.clone()),
+            None => TimeSeries::default(),
+        }
+    }
+}
+
+// the Monday of the ISO week of the date, which stands for the whole week
+pub fn iso_week(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+// first to last, inclusive, nothing if last is before first
+pub fn days(first: NaiveDate, last: NaiveDate) -> impl Iterator<Item = NaiveDate> {
+    (0..=(last - first).num_days()).map(move |offset| first + Duration::days(offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2020, 12, day).unwrap()
+    }
+
+    fn counts(values: &[(u32, i64)]) -> TimeSeries<i64> {
+        values.iter().map(|&(day, value)| (date(day), value)).collect()
+    }
+
+    #[test]
+    fn calendar_starts_the_day_after_from() {
+        let calendar = Calendar::new(date(1), date(4));
+        assert_eq!(calendar.days().collect::<Vec<NaiveDate>>(), vec![date(2), date(3), date(4)]);
+        assert_eq!(Calendar::new(date(4), date(4)).days().count(), 0);
+    }
+
+    #[test]
+    fn calendar_fill_gives_every_day_up_to_the_last_of_the_series_a_value() {
+        let calendar = Calendar::new(date(1), date(10));
+        assert_eq!(calendar.fill(&counts(&[(3, 5), (5, 7)]), 0), counts(&[(2, 0), (3, 5), (4, 0), (5, 7)]));
+        assert!(calendar.fill(&TimeSeries::<i64>::default(), 0).is_empty());
+    }
+
+    #[test]
+    fn reindex_fills_the_missing_days_and_drops_the_others() {
+        let series = counts(&[(1, 1), (3, 3), (6, 6)]);
+        let reindexed = series.reindex(days(date(2), date(4)), |date| -(date.day() as i64));
+        assert_eq!(reindexed, counts(&[(2, -2), (3, 3), (4, -4)]));
+    }
+
+    #[test]
+    fn gaps_split_after_and_iso_week() {
+        let mut series = counts(&[(1, 1), (3, 3), (6, 6)]);
+        assert_eq!(series.gaps(), vec![date(2), date(4), date(5)]);
+        assert_eq!(series.split_after(date(3)), counts(&[(6, 6)]));
+        assert_eq!(series, counts(&[(1, 1), (3, 3)]));
+        // the first of December 2020 is a Tuesday
+        assert_eq!(iso_week(date(1)), NaiveDate::from_ymd_opt(2020, 11, 30).unwrap());
+        assert_eq!(iso_week(date(7)), date(7));
+    }
+
+    #[test]
+    fn union_and_intersection() {
+        let a = counts(&[(1, 1), (2, 2)]);
+        let b = counts(&[(2, 20), (3, 30)]);
+        assert_eq!(a.intersection(&b), [(date(2), (2, 20))].iter().copied().collect());
+        assert_eq!(a.union(&b).values().cloned().collect::<Vec<(Option<i64>, Option<i64>)>>(),
+            vec![(Some(1), None), (Some(2), Some(20)), (None, Some(30))]);
+    }
+}